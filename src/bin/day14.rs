@@ -4,5 +4,9 @@ use aoc2017::puzzles::day14;
 fn main() {
   let disk = day14::Disk::new("amgozmfv");
   println!("{} cells used.", disk.used());
-  println!("{} regions found.", disk.regions());
+  let regions = disk.label();
+  println!("{} regions found.", regions.count());
+  if let Some((region, size)) = regions.largest() {
+    println!("Region {} is the largest, with {} cells.", region, size);
+  }
 }
@@ -2,37 +2,36 @@ extern crate aoc2017;
 use aoc2017::puzzles::day16;
 
 use std::io;
-use std::io::BufRead;
+use std::io::Read;
+use std::process;
 
 fn main() {
   let stdin = io::stdin();
-
-  let programs: Vec<char> = "abcdefghijklmnop".chars().collect();
-
-  let steps: Vec<String> = stdin
+  let mut input = String::new();
+  stdin
     .lock()
-    .split(',' as u8)
-    .map(|s| String::from_utf8(s.unwrap()).unwrap())
-    .collect();
+    .read_to_string(&mut input)
+    .expect("Reading dance moves.");
+
+  let lineup = day16::LineUp::letters(16).expect("16 letters");
+  let dance = match day16::parse_dance(&input).and_then(|steps| lineup.compile(&steps)) {
+    Ok(dance) => dance,
+    Err(e) => {
+      eprintln!("Error: {}", e);
+      process::exit(1);
+    }
+  };
 
   {
-    let mut p1: Vec<char> = programs.iter().cloned().collect();
-    day16::dance(&mut p1, &steps);
-    println!("Part 1: {}", day16::dance_string(&p1));
+    let mut p1 = lineup.clone();
+    p1.perform(&dance).unwrap();
+    println!("Part 1: {}", p1);
   }
 
   {
-    println!("Part 2:");
-    let mut p2: Vec<char> = programs.iter().cloned().collect();
-    let (offset, cycle) = day16::dance_cycle(&mut p2, &steps);
-    println!("Cycle: {}, Offset: {}", cycle, offset);
-    let ndances = ((1000000000 as u64 - (offset as u64)) % (cycle as u64)) as usize + offset;
-    println!("We should dance {} times", ndances);
-
-    let mut p3: Vec<char> = programs.iter().cloned().collect();
-    for _i in 0..ndances {
-      day16::dance(&mut p3, &steps);
-    }
-    println!("Final arangement: {}", day16::dance_string(&p3));
+    let mut p2 = lineup.clone();
+    p2.perform(&dance.pow(1000000000)).unwrap();
+    println!("Part 2: {}", p2);
+    println!("The dance repeats every {} times.", dance.period());
   }
 }
@@ -3,8 +3,10 @@ use aoc2017::puzzles::day17;
 
 fn main() {
   let input = 382;
-  let locked = day17::spinlock(2018, input);
-  println!("Part 1: {}", day17::getnextitem(&locked, 2017));
+  let mut lock = day17::Spinlock::new(input);
+  lock.run_to(2017);
+  println!("Part 1: {}", lock.value_after(2017).unwrap());
 
-  println!("Part 2: {}", day17::spinlock_afterzero(50000001, input));
+  lock.run_to(50_000_000);
+  println!("Part 2: {}", lock.value_after(0).unwrap());
 }
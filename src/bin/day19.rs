@@ -1,14 +1,42 @@
 extern crate aoc2017;
 use aoc2017::puzzles::day19;
-use aoc2017::puzzles::day19::Traverseable;
+use std::env;
 use std::io;
-use std::io::BufRead;
+use std::io::Read;
+use std::process;
 
 fn main() {
-  let stdin = io::stdin();
+  let mut diagram = String::new();
+  io::stdin().read_to_string(&mut diagram).unwrap();
 
-  let map = day19::create_map(stdin.lock().lines());
-  let seen_letters: String = map.traverse_letters().collect();
-  println!("Part 1: {}", seen_letters);
-  println!("Part 2: {} steps", map.traverse().count() + 1);
+  let network: day19::Network = diagram.parse().unwrap_or_else(|e| {
+    eprintln!("Error reading the network: {}", e);
+    process::exit(1);
+  });
+
+  // Passing "ansi" or "markers" draws the route instead.
+  let highlight = match env::args().nth(1).as_ref().map(|s| s.as_str()) {
+    None => None,
+    Some("ansi") => Some(day19::Highlight::Ansi),
+    Some("markers") => Some(day19::Highlight::Markers),
+    Some(other) => {
+      eprintln!("Unknown highlight {}: use ansi or markers", other);
+      process::exit(1);
+    }
+  };
+  if let Some(highlight) = highlight {
+    print!("{}", network.overlay(highlight));
+    return;
+  }
+
+  match network.letters().and_then(|l| Ok((l, network.length()?))) {
+    Ok((letters, length)) => {
+      println!("Part 1: {}", letters);
+      println!("Part 2: {} steps", length);
+    }
+    Err(e) => {
+      eprintln!("Error following the network: {}", e);
+      process::exit(1);
+    }
+  }
 }
@@ -1,24 +1,21 @@
 extern crate aoc2017;
 use aoc2017::puzzles::day20;
 use std::io;
-use std::io::BufRead;
+use std::io::Read;
+use std::process;
 
 fn main() {
-  let stdin = io::stdin();
+  let mut input = String::new();
+  io::stdin().read_to_string(&mut input).unwrap();
 
-  let particles: Vec<day20::Particle> = stdin
-    .lock()
-    .lines()
-    .map(|line| day20::Particle::parse(&line.unwrap()).unwrap())
-    .collect();
+  let particles = day20::parse_particles(&input).unwrap_or_else(|(line, e)| {
+    eprintln!("Error on line {}: {}", line, e);
+    process::exit(1);
+  });
 
   let pid = day20::find_closest(particles.clone(), false);
   println!("Particle {} will stay closest to the origin.", pid);
 
-  let (_n, ps) = day20::simulate(particles.clone(), None, true)
-    .skip(100)
-    .skip_while(|&(_i, ref ps)| ps.iter().all(|x| x.settled()))
-    .nth(100)
-    .unwrap();
-  println!("{} Particles remain after stability is achieved.", ps.len());
+  let survivors = day20::survivors(&particles);
+  println!("{} Particles remain after all collisions.", survivors.len());
 }
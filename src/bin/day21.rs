@@ -1,8 +1,10 @@
 extern crate aoc2017;
+use aoc2017::netpbm;
 use aoc2017::puzzles::day21;
 
 extern crate failure;
 
+use std::env;
 use std::io;
 use std::io::BufRead;
 use std::process;
@@ -16,28 +18,54 @@ fn load_patterns() -> Result<day21::PatternSet, failure::Error> {
   Ok(patterns)
 }
 
+/// Write each generation of artwork up to `n` as a PBM image.
+fn write_frames(
+  rulebook: &day21::Rulebook,
+  n: usize,
+  directory: &str,
+) -> Result<usize, failure::Error> {
+  let palette = netpbm::Monochrome;
+  let mut frames = netpbm::FrameWriter::new(directory, "art", netpbm::Format::Pbm, palette)?;
+  for artwork in rulebook.generations(&day21::Patch::default()).take(n + 1) {
+    frames.write(&artwork?)?;
+  }
+  Ok(frames.frames())
+}
+
 fn main() {
-  let patterns = match load_patterns() {
-    Ok(p) => p,
+  let rulebook = match load_patterns().and_then(|p| Ok(day21::Rulebook::new(&p)?)) {
+    Ok(r) => r,
     Err(e) => {
       eprintln!("Error: {:?}", e);
       process::exit(1);
     }
   };
 
-  match day21::after_n(&patterns, 5).map(|p| day21::on(&p)) {
-    Ok(ans) => println!("Part 1: {} cells are on after 5 iterations", ans),
-    Err(e) => {
-      eprintln!("Error: {:?}", e);
-      process::exit(1);
-    }
-  };
+  // An extra argument asks for some other number of iterations,
+  // and a directory after it writes every generation up to it.
+  let mut iterations = vec![("Part 1", 5), ("Part 2", 18)];
+  let extra = env::args().nth(1).map(|n| n.parse().expect("Number of iterations"));
+  if let Some(n) = extra {
+    iterations.push(("Extra", n));
+  }
 
-  match day21::after_n(&patterns, 18).map(|p| day21::on(&p)) {
-    Ok(ans) => println!("Part 2: {} cells are on after 18 iterations", ans),
-    Err(e) => {
-      eprintln!("Error: {:?}", e);
-      process::exit(1);
+  for (label, n) in iterations {
+    match rulebook.count_on(&day21::Patch::default(), n) {
+      Ok(ans) => println!("{}: {} cells are on after {} iterations", label, ans, n),
+      Err(e) => {
+        eprintln!("Error: {:?}", e);
+        process::exit(1);
+      }
+    };
+  }
+
+  if let (Some(n), Some(directory)) = (extra, env::args().nth(2)) {
+    match write_frames(&rulebook, n, &directory) {
+      Ok(frames) => println!("Wrote {} frames to {}", frames, directory),
+      Err(e) => {
+        eprintln!("Error: {:?}", e);
+        process::exit(1);
+      }
     }
-  };
+  }
 }
@@ -1,9 +1,48 @@
 extern crate aoc2017;
+use aoc2017::netpbm;
 use aoc2017::puzzles::day22;
 
+use std::env;
 use std::io;
 use std::io::Read;
 
+/// Write a frame of the virus every `FRAME_STEP` bursts.
+const FRAME_STEP: usize = 100;
+
+/// The number of nodes around the origin to include in each frame.
+const FRAME_RADIUS: usize = 50;
+
+/// Colors for states past the named ones, which cycle.
+const EXTRA_COLORS: [netpbm::Rgb; 3] = [
+  netpbm::Rgb(0, 160, 0),
+  netpbm::Rgb(160, 0, 160),
+  netpbm::Rgb(0, 160, 160),
+];
+
+fn color(state: &usize) -> netpbm::Rgb {
+  match *state {
+    0 => netpbm::Rgb::white(),
+    1 => netpbm::Rgb(200, 0, 0),
+    2 => netpbm::Rgb(0, 0, 200),
+    3 => netpbm::Rgb(255, 200, 0),
+    n => EXTRA_COLORS[(n - 4) % EXTRA_COLORS.len()],
+  }
+}
+
+fn write_frames(cluster_init: &str, rules: day22::RuleTable, directory: &str) -> io::Result<usize> {
+  let mut frames = netpbm::FrameWriter::new(directory, "virus", netpbm::Format::Ppm, color)?;
+
+  let mut cluster: day22::Cluster = cluster_init.parse().expect("Trouble parsing");
+  let mut virus = cluster.travel_with(rules).expect("Virus rule");
+  for burst in 0..10000 {
+    if burst % FRAME_STEP == 0 {
+      frames.write(&virus.cluster().window(FRAME_RADIUS))?;
+    }
+    virus.next();
+  }
+  Ok(frames.frames())
+}
+
 fn main() {
   let stdin = io::stdin();
   let mut cluster_init = String::new();
@@ -35,4 +74,16 @@ fn main() {
         .sum::<usize>()
     );
   }
+
+  // Frames show the part 1 virus, or any other
+  // given as a rule like "LR" after the directory.
+  if let Some(directory) = env::args().nth(1) {
+    let rules: day22::RuleTable = env::args()
+      .nth(2)
+      .unwrap_or_else(|| "LR".to_string())
+      .parse()
+      .expect("Virus rule");
+    let n = write_frames(&cluster_init, rules, &directory).expect("Writing frames");
+    println!("Wrote {} frames to {}", n, directory);
+  }
 }
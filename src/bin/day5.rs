@@ -5,18 +5,16 @@ use std::io::prelude::*;
 
 fn main() {
   let stdin = io::stdin();
-  let mut instructions: Vec<isize> = stdin
+  let offsets: Vec<i32> = stdin
     .lock()
     .lines()
-    .map(|l| l.unwrap().parse::<isize>().unwrap())
+    .map(|l| l.unwrap().trim().parse::<i32>().unwrap())
     .collect();
 
-  let mut instructions_fancy = instructions.clone();
-
-  let nsteps = day5::run_instructions(&mut instructions, 0, day5::jump_instruction).unwrap();
+  let nsteps = day5::escape(&offsets, day5::Increment).unwrap();
   println!("It took {} steps to exit the maze.", nsteps);
 
-  let nsteps_fancy = day5::run_instructions(&mut instructions_fancy, 0, day5::jump_fancy).unwrap();
+  let nsteps_fancy = day5::escape(&offsets, day5::Strange).unwrap();
   println!(
     "It took {} steps to exit the maze with fancy instructions.",
     nsteps_fancy
@@ -1,5 +1,6 @@
 extern crate aoc2017;
 use aoc2017::puzzles::day6;
+use std::env;
 use std::io;
 use std::io::prelude::*;
 use std::process;
@@ -20,8 +21,34 @@ fn main() {
     .collect();
 
   println!("Memory {:?}", memory);
+
+  // Naming a policy prints the whole reallocation
+  // sequence with that policy instead.
+  match env::args().nth(1).as_ref().map(|s| s.as_str()) {
+    None => {}
+    Some("largest") => return show_history(memory, day6::Largest),
+    Some("smallest") => return show_history(memory, day6::Smallest),
+    Some("neighbors") => return show_history(memory, day6::Neighbors),
+    Some(policy) => match policy.parse() {
+      Ok(chunk) if chunk > 0 => return show_history(memory, day6::Chunked(chunk)),
+      _ => {
+        eprintln!("Unknown policy {}: use largest, smallest, neighbors or a chunk size", policy);
+        process::exit(1);
+      }
+    },
+  }
+
   let (nsteps, ncycle) = day6::reallocate_many(memory);
 
   println!("In {} steps, found a repeat state.", nsteps);
   println!("The cycle is {} steps long.", ncycle);
 }
+
+fn show_history<P: day6::Policy>(memory: Vec<usize>, policy: P) {
+  let mut memory = day6::Memory::new(memory, policy).with_history();
+  let (mu, lambda) = memory.run();
+  for (step, banks) in memory.history().unwrap().iter().enumerate() {
+    println!("{:>6}: {:?}", step, banks);
+  }
+  println!("The state at step {} repeats every {} steps.", mu, lambda);
+}
@@ -1,14 +1,17 @@
 extern crate aoc2017;
 use aoc2017::puzzles::day9;
 use std::io;
-use std::io::prelude::*;
+use std::process;
 
 fn main() {
-  let mut stdin = io::stdin();
-  let mut text = String::new();
-  stdin.read_to_string(&mut text).unwrap();
+  let stdin = io::stdin();
+  let mut stream = day9::StreamProcessor::new();
+  stream.process(stdin.lock()).expect("Reading the stream");
 
-  let (score, garbage) = day9::procress_stream(&text);
-  println!("Score: {}", score);
-  println!("Garbage: {}", garbage);
+  if !stream.is_complete() {
+    eprintln!("Error: the stream ended inside a group or garbage");
+    process::exit(1);
+  }
+  println!("Score: {}", stream.score());
+  println!("Garbage: {}", stream.garbage());
 }
@@ -0,0 +1,201 @@
+//! Cycle detection for iterated functions.
+//!
+//! Given a starting state `x0` and a function `f`, the
+//! sequence `x0, f(x0), f(f(x0)), ...` eventually repeats
+//! if there are finitely many states. The sequence is then
+//! described by `(mu, lambda)`, where `mu` is the index of
+//! the first state in the cycle and `lambda` is the length
+//! of the cycle.
+//!
+//! `brent` and `floyd` only keep a couple of states in memory,
+//! so they work for large states at the cost of evaluating `f`
+//! more than once per step. `hashed` evaluates `f` once per
+//! step but remembers every state it has seen.
+//!
+//! None of these return if the sequence never repeats.
+
+use std::collections::HashMap;
+use std::hash::Hash;
+
+/// Find `(mu, lambda)` with Brent's algorithm.
+pub fn brent<S, F>(start: &S, f: F) -> (usize, usize)
+where
+  S: Clone + PartialEq,
+  F: Fn(&S) -> S,
+{
+  // Find lambda, by moving the tortoise up to the
+  // hare at each power of two until the hare meets it.
+  let mut power = 1;
+  let mut lambda = 1;
+  let mut tortoise = start.clone();
+  let mut hare = f(start);
+  while tortoise != hare {
+    if power == lambda {
+      tortoise = hare.clone();
+      power *= 2;
+      lambda = 0;
+    }
+    hare = f(&hare);
+    lambda += 1;
+  }
+
+  // Find mu, with the hare lambda steps ahead.
+  let mut tortoise = start.clone();
+  let mut hare = start.clone();
+  for _ in 0..lambda {
+    hare = f(&hare);
+  }
+  let mut mu = 0;
+  while tortoise != hare {
+    tortoise = f(&tortoise);
+    hare = f(&hare);
+    mu += 1;
+  }
+  (mu, lambda)
+}
+
+/// Find `(mu, lambda)` with Floyd's tortoise and hare.
+pub fn floyd<S, F>(start: &S, f: F) -> (usize, usize)
+where
+  S: Clone + PartialEq,
+  F: Fn(&S) -> S,
+{
+  // Find a state in the cycle, where the
+  // hare has lapped the tortoise.
+  let mut tortoise = f(start);
+  let mut hare = f(&tortoise);
+  while tortoise != hare {
+    tortoise = f(&tortoise);
+    hare = f(&f(&hare));
+  }
+
+  // The distance from the start to the cycle is the same
+  // as the distance from the meeting point to the cycle.
+  let mut mu = 0;
+  let mut tortoise = start.clone();
+  while tortoise != hare {
+    tortoise = f(&tortoise);
+    hare = f(&hare);
+    mu += 1;
+  }
+
+  let mut lambda = 1;
+  let mut hare = f(&tortoise);
+  while tortoise != hare {
+    hare = f(&hare);
+    lambda += 1;
+  }
+  (mu, lambda)
+}
+
+/// Find `(mu, lambda)` by remembering the
+/// index of every state seen so far.
+pub fn hashed<S, F>(start: &S, f: F) -> (usize, usize)
+where
+  S: Clone + Eq + Hash,
+  F: Fn(&S) -> S,
+{
+  let mut seen = HashMap::new();
+  let mut state = start.clone();
+  for i in 0.. {
+    let next = f(&state);
+    if let Some(mu) = seen.insert(state, i) {
+      return (mu, i - mu);
+    }
+    state = next;
+  }
+  unreachable!()
+}
+
+/// The state after `n` steps, using Brent's algorithm to skip
+/// over whole cycles. If there is no cycle in the first `n`
+/// steps, this is the same as applying `f` `n` times.
+pub fn nth_state<S, F>(start: &S, f: F, n: usize) -> S
+where
+  S: Clone + PartialEq,
+  F: Fn(&S) -> S,
+{
+  // Brent's search for lambda moves the hare one step at a
+  // time, so if it reaches step n first, it has the answer.
+  if n == 0 {
+    return start.clone();
+  }
+  let mut power = 1;
+  let mut lambda = 1;
+  let mut tortoise = start.clone();
+  let mut hare = f(start);
+  let mut steps = 1;
+  while tortoise != hare {
+    if steps == n {
+      return hare;
+    }
+    if power == lambda {
+      tortoise = hare.clone();
+      power *= 2;
+      lambda = 0;
+    }
+    hare = f(&hare);
+    lambda += 1;
+    steps += 1;
+  }
+
+  // The hare is in the cycle, at step `steps`, so
+  // stepping lambda at a time doesn't change anything.
+  for _ in 0..(n - steps) % lambda {
+    hare = f(&hare);
+  }
+  hare
+}
+
+#[cfg(test)]
+mod test {
+
+  use super::*;
+
+  /// A sequence with mu = 3 and lambda = 5:
+  /// 0, 1, 2, 3, 4, 5, 6, 7, 3, 4, ...
+  fn rho(x: &u32) -> u32 {
+    if *x == 7 {
+      3
+    } else {
+      x + 1
+    }
+  }
+
+  #[test]
+  fn find_cycle() {
+    assert_eq!(brent(&0, rho), (3, 5));
+    assert_eq!(floyd(&0, rho), (3, 5));
+    assert_eq!(hashed(&0, rho), (3, 5));
+  }
+
+  #[test]
+  fn find_cycle_from_inside() {
+    assert_eq!(brent(&5, rho), (0, 5));
+    assert_eq!(floyd(&5, rho), (0, 5));
+    assert_eq!(hashed(&5, rho), (0, 5));
+  }
+
+  #[test]
+  fn fixed_point() {
+    let f = |x: &u32| x / 2;
+    assert_eq!(brent(&100, f), (7, 1));
+    assert_eq!(floyd(&100, f), (7, 1));
+    assert_eq!(hashed(&100, f), (7, 1));
+  }
+
+  #[test]
+  fn agree_with_iteration() {
+    let mut state = 0;
+    for n in 0..50 {
+      assert_eq!(nth_state(&0, rho, n), state, "step {}", n);
+      state = rho(&state);
+    }
+    assert_eq!(nth_state(&0, rho, 1_000_000_000), 3 + (1_000_000_000 - 3) % 5);
+  }
+
+  #[test]
+  fn nth_state_without_cycle() {
+    assert_eq!(nth_state(&0u64, |x| x + 1, 1000), 1000);
+  }
+}
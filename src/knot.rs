@@ -36,26 +36,26 @@ impl Knot {
     return &self.hash;
   }
 
-  /// The binary digest of this Knot
-  /// in xor-d chuncks of 16 as binary digits.
-  pub fn bindigest(&self) -> String {
+  /// The dense hash of this Knot,
+  /// as xor-d chunks of 16 bytes.
+  pub fn dense(&self) -> Vec<u8> {
     self
       .hash
       .chunks(16)
       .map(|chunk| chunk.iter().fold(0, |acc, &x| acc ^ x))
-      .map(|x| format!("{:08b}", x))
       .collect()
   }
 
+  /// The binary digest of this Knot
+  /// in xor-d chuncks of 16 as binary digits.
+  pub fn bindigest(&self) -> String {
+    self.dense().iter().map(|x| format!("{:08b}", x)).collect()
+  }
+
   /// The hexadecimal digest of this Knot
   /// in xor-d chuncks of 16 as hex.
   pub fn hexdigest(&self) -> String {
-    self
-      .hash
-      .chunks(16)
-      .map(|chunk| chunk.iter().fold(0, |acc, &x| acc ^ x))
-      .map(|x| format!("{:02x}", x))
-      .collect()
+    self.dense().iter().map(|x| format!("{:02x}", x)).collect()
   }
 
   /// The simple-digest is the prodcut of the first
@@ -10,7 +10,9 @@ extern crate test;
 extern crate failure;
 
 mod hexagons;
+pub mod cycle;
 pub mod graph;
 pub mod puzzles;
 pub mod knot;
+pub mod netpbm;
 pub mod vm;
@@ -0,0 +1,334 @@
+//! Image output for grid puzzles.
+//!
+//! Writes any `Grid` to one of the binary Netpbm formats
+//! (PBM, PGM or PPM), mapping cells to colors with a `Palette`.
+//! These files are simple enough that most image viewers
+//! will open them directly.
+
+use std::fs;
+use std::io;
+use std::io::Write;
+use std::path;
+
+/// A rectangular grid of cells which can be drawn as an image.
+pub trait Grid {
+  /// The type of each cell in the grid.
+  type Cell;
+
+  /// The number of columns in the grid.
+  fn width(&self) -> usize;
+
+  /// The number of rows in the grid.
+  fn height(&self) -> usize;
+
+  /// The cell at column `x` and row `y`.
+  fn cell(&self, x: usize, y: usize) -> Self::Cell;
+}
+
+/// A color, as red, green and blue components.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rgb(pub u8, pub u8, pub u8);
+
+impl Rgb {
+  pub fn black() -> Self {
+    Rgb(0, 0, 0)
+  }
+
+  pub fn white() -> Self {
+    Rgb(255, 255, 255)
+  }
+
+  /// The brightness of this color, using the
+  /// integer approximation of the Rec. 601 luma.
+  pub fn luma(&self) -> u8 {
+    ((299 * self.0 as u32 + 587 * self.1 as u32 + 114 * self.2 as u32) / 1000) as u8
+  }
+}
+
+/// Maps grid cells to colors.
+pub trait Palette<T> {
+  fn color(&self, cell: &T) -> Rgb;
+}
+
+impl<T, F> Palette<T> for F
+where
+  F: Fn(&T) -> Rgb,
+{
+  fn color(&self, cell: &T) -> Rgb {
+    self(cell)
+  }
+}
+
+/// The classic palette for boolean grids:
+/// `true` cells are black, `false` cells are white.
+pub struct Monochrome;
+
+impl Palette<bool> for Monochrome {
+  fn color(&self, cell: &bool) -> Rgb {
+    if *cell {
+      Rgb::black()
+    } else {
+      Rgb::white()
+    }
+  }
+}
+
+/// A palette for enumerated cells, built from
+/// a list of cell values and their colors.
+/// Cells not in the list get the default color.
+pub struct Colors<T> {
+  colors: Vec<(T, Rgb)>,
+  default: Rgb,
+}
+
+impl<T> Colors<T>
+where
+  T: PartialEq,
+{
+  pub fn new(default: Rgb) -> Self {
+    Colors {
+      colors: Vec::new(),
+      default,
+    }
+  }
+
+  /// Add a color for a cell value.
+  pub fn with(mut self, cell: T, color: Rgb) -> Self {
+    self.colors.push((cell, color));
+    self
+  }
+}
+
+impl<T> Palette<T> for Colors<T>
+where
+  T: PartialEq,
+{
+  fn color(&self, cell: &T) -> Rgb {
+    self
+      .colors
+      .iter()
+      .find(|&(c, _)| c == cell)
+      .map(|&(_, color)| color)
+      .unwrap_or(self.default)
+  }
+}
+
+/// The Netpbm image formats.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+  /// Portable bitmap, where each pixel is black or white.
+  /// Pixels with a luma below half brightness are black.
+  Pbm,
+
+  /// Portable graymap, using the luma of each pixel.
+  Pgm,
+
+  /// Portable pixmap, in full color.
+  Ppm,
+}
+
+impl Format {
+  /// The conventional file extension for this format.
+  pub fn extension(&self) -> &'static str {
+    match *self {
+      Format::Pbm => "pbm",
+      Format::Pgm => "pgm",
+      Format::Ppm => "ppm",
+    }
+  }
+
+  fn magic(&self) -> &'static str {
+    match *self {
+      Format::Pbm => "P4",
+      Format::Pgm => "P5",
+      Format::Ppm => "P6",
+    }
+  }
+}
+
+/// Write a grid as a Netpbm image.
+pub fn write<G, P, W>(grid: &G, palette: &P, format: Format, writer: &mut W) -> io::Result<()>
+where
+  G: Grid,
+  P: Palette<G::Cell>,
+  W: Write,
+{
+  let (width, height) = (grid.width(), grid.height());
+  write!(writer, "{}\n{} {}\n", format.magic(), width, height)?;
+  if format != Format::Pbm {
+    writeln!(writer, "255")?;
+  }
+
+  let mut row = Vec::new();
+  for y in 0..height {
+    row.clear();
+    match format {
+      Format::Pbm => {
+        row.resize((width + 7) / 8, 0);
+        for x in 0..width {
+          if palette.color(&grid.cell(x, y)).luma() < 128 {
+            row[x / 8] |= 0x80 >> (x % 8);
+          }
+        }
+      }
+      Format::Pgm => {
+        row.extend((0..width).map(|x| palette.color(&grid.cell(x, y)).luma()));
+      }
+      Format::Ppm => for x in 0..width {
+        let Rgb(r, g, b) = palette.color(&grid.cell(x, y));
+        row.extend(&[r, g, b]);
+      },
+    }
+    writer.write_all(&row)?;
+  }
+  Ok(())
+}
+
+/// Write a grid as a Netpbm image to a file.
+pub fn save<G, P, Q>(grid: &G, palette: &P, format: Format, filename: Q) -> io::Result<()>
+where
+  G: Grid,
+  P: Palette<G::Cell>,
+  Q: AsRef<path::Path>,
+{
+  let mut writer = io::BufWriter::new(fs::File::create(filename)?);
+  write(grid, palette, format, &mut writer)?;
+  writer.flush()
+}
+
+/// Writes a numbered sequence of images, one per frame of
+/// a simulation, into a directory. Frames are named
+/// `{prefix}-{frame:06}.{extension}`.
+pub struct FrameWriter<P> {
+  directory: path::PathBuf,
+  prefix: String,
+  format: Format,
+  palette: P,
+  frame: usize,
+}
+
+impl<P> FrameWriter<P> {
+  /// Create a frame writer, creating the
+  /// output directory if it doesn't exist.
+  pub fn new<Q>(directory: Q, prefix: &str, format: Format, palette: P) -> io::Result<Self>
+  where
+    Q: AsRef<path::Path>,
+  {
+    fs::create_dir_all(&directory)?;
+    Ok(FrameWriter {
+      directory: directory.as_ref().to_path_buf(),
+      prefix: prefix.to_string(),
+      format,
+      palette,
+      frame: 0,
+    })
+  }
+
+  /// The number of frames written so far.
+  pub fn frames(&self) -> usize {
+    self.frame
+  }
+
+  /// Write the next frame, returning the path it was written to.
+  pub fn write<G>(&mut self, grid: &G) -> io::Result<path::PathBuf>
+  where
+    G: Grid,
+    P: Palette<G::Cell>,
+  {
+    let filename = self.directory.join(format!(
+      "{}-{:06}.{}",
+      self.prefix,
+      self.frame,
+      self.format.extension()
+    ));
+    save(grid, &self.palette, self.format, &filename)?;
+    self.frame += 1;
+    Ok(filename)
+  }
+
+  /// Write every grid from an iterator as a frame.
+  pub fn write_all<G, I>(&mut self, grids: I) -> io::Result<()>
+  where
+    G: Grid,
+    P: Palette<G::Cell>,
+    I: IntoIterator<Item = G>,
+  {
+    for grid in grids {
+      self.write(&grid)?;
+    }
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  struct Checkers(usize);
+
+  impl Grid for Checkers {
+    type Cell = bool;
+
+    fn width(&self) -> usize {
+      self.0
+    }
+
+    fn height(&self) -> usize {
+      2
+    }
+
+    fn cell(&self, x: usize, y: usize) -> bool {
+      (x + y) % 2 == 0
+    }
+  }
+
+  fn render<P: Palette<bool>>(grid: &Checkers, palette: &P, format: Format) -> Vec<u8> {
+    let mut image = Vec::new();
+    write(grid, palette, format, &mut image).unwrap();
+    image
+  }
+
+  #[test]
+  fn write_pbm() {
+    let image = render(&Checkers(10), &Monochrome, Format::Pbm);
+    let mut expected = b"P4\n10 2\n".to_vec();
+    expected.extend(&[0b10101010, 0b10000000, 0b01010101, 0b01000000]);
+    assert_eq!(image, expected);
+  }
+
+  #[test]
+  fn write_pgm() {
+    let image = render(&Checkers(3), &Monochrome, Format::Pgm);
+    let mut expected = b"P5\n3 2\n255\n".to_vec();
+    expected.extend(&[0, 255, 0, 255, 0, 255]);
+    assert_eq!(image, expected);
+  }
+
+  #[test]
+  fn write_ppm() {
+    let palette = Colors::new(Rgb(0, 0, 255)).with(true, Rgb(255, 0, 0));
+    let image = render(&Checkers(2), &palette, Format::Ppm);
+    let mut expected = b"P6\n2 2\n255\n".to_vec();
+    expected.extend(&[255, 0, 0, 0, 0, 255, 0, 0, 255, 255, 0, 0]);
+    assert_eq!(image, expected);
+  }
+
+  #[test]
+  fn write_frames() {
+    let directory = ::std::env::temp_dir().join("aoc2017-netpbm-frames");
+    let mut frames = FrameWriter::new(&directory, "checkers", Format::Pbm, Monochrome).unwrap();
+    frames.write_all((1..4).map(Checkers)).unwrap();
+    assert_eq!(frames.frames(), 3);
+
+    let last = fs::read(directory.join("checkers-000002.pbm")).unwrap();
+    assert_eq!(&last[..7], b"P4\n3 2\n");
+    fs::remove_dir_all(&directory).unwrap();
+  }
+
+  #[test]
+  fn closure_palette() {
+    let palette = |c: &bool| if *c { Rgb(10, 10, 10) } else { Rgb(200, 200, 200) };
+    let image = render(&Checkers(1), &palette, Format::Pgm);
+    assert_eq!(&image[image.len() - 2..], &[10, 200]);
+  }
+}
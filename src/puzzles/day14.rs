@@ -1,70 +1,195 @@
+//! Day 14: Disk Defragmentation
+//!
+//! The disk is a 128x128 grid of bits, where each row
+//! is the dense knot hash of the key and the row number.
+
 use super::super::knot;
-use std::collections::HashSet;
+use super::super::netpbm;
 use std::collections::VecDeque;
+use std::fmt;
+
+/// The width and height of the disk grid.
+pub const DISK_SIZE: usize = 128;
 
+/// Characters used to render region labels, cycling by region
+/// id. This is only a visual aid: regions may touch diagonally,
+/// and two such regions whose ids differ by a multiple of the
+/// glyph count are drawn with the same character.
+const REGION_GLYPHS: &[u8] =
+  b"abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789";
+
+/// A 128x128 grid of used and free squares,
+/// with each row packed into a single integer.
 pub struct Disk {
-  data: Vec<knot::Knot>,
+  rows: Vec<u128>,
 }
 
 impl Disk {
   pub fn new(key: &str) -> Disk {
-    let mut disk = Disk { data: Vec::new() };
-    for row in 0..128 {
-      let rowkey = format!("{}-{}", key, row);
-      let knot = knot::Knot::standard(&rowkey);
-      disk.data.push(knot)
-    }
-    disk
+    let rows = (0..DISK_SIZE)
+      .map(|row| {
+        let rowkey = format!("{}-{}", key, row);
+        knot::Knot::standard(&rowkey)
+          .dense()
+          .iter()
+          .fold(0, |acc, &b| (acc << 8) | b as u128)
+      })
+      .collect();
+    Disk { rows }
   }
 
-  pub fn used(&self) -> usize {
-    self
-      .data
-      .iter()
-      .map(|row| row.bindigest().chars().filter(|b| *b == '1').count())
-      .sum()
+  /// Is the square at column `x` and row `y` used?
+  pub fn get(&self, x: usize, y: usize) -> bool {
+    (self.rows[y] >> (DISK_SIZE - 1 - x)) & 1 == 1
   }
 
-  pub fn show(&self) {
-    for row in &self.data {
-      println!("{}", row.bindigest())
-    }
+  pub fn used(&self) -> usize {
+    self.rows.iter().map(|row| row.count_ones() as usize).sum()
   }
 
-  pub fn regions(&self) -> usize {
-    let mut regions = 0;
-    let mut grid: HashSet<(isize, isize)> = HashSet::new();
-    for (y, row) in self.data.iter().enumerate() {
-      for (x, cell) in row.bindigest().chars().enumerate() {
-        if cell == '1' {
-          grid.insert((x as isize, y as isize));
+  /// Label each used square with the
+  /// region it belongs to.
+  pub fn label(&self) -> Regions {
+    let mut labels = vec![None; DISK_SIZE * DISK_SIZE];
+    let mut sizes = Vec::new();
+    let mut queue = VecDeque::new();
+
+    for y in 0..DISK_SIZE {
+      for x in 0..DISK_SIZE {
+        if !self.get(x, y) || labels[y * DISK_SIZE + x].is_some() {
+          continue;
         }
+
+        let region = sizes.len();
+        let mut size = 0;
+        labels[y * DISK_SIZE + x] = Some(region);
+        queue.push_back((x, y));
+
+        while let Some((x, y)) = queue.pop_front() {
+          size += 1;
+          let mut neighbors = Vec::with_capacity(4);
+          if x > 0 {
+            neighbors.push((x - 1, y));
+          }
+          if x + 1 < DISK_SIZE {
+            neighbors.push((x + 1, y));
+          }
+          if y > 0 {
+            neighbors.push((x, y - 1));
+          }
+          if y + 1 < DISK_SIZE {
+            neighbors.push((x, y + 1));
+          }
+          for (nx, ny) in neighbors {
+            if self.get(nx, ny) && labels[ny * DISK_SIZE + nx].is_none() {
+              labels[ny * DISK_SIZE + nx] = Some(region);
+              queue.push_back((nx, ny));
+            }
+          }
+        }
+
+        sizes.push(size);
       }
     }
 
-    let mut queue = VecDeque::new();
+    Regions { labels, sizes }
+  }
 
-    loop {
-      let cell = {
-        match grid.iter().next() {
-          Some(c) => *c,
-          None => break,
-        }
-      };
+  pub fn regions(&self) -> usize {
+    self.label().count()
+  }
+}
 
-      grid.remove(&cell);
-      regions += 1;
-      queue.push_back(cell);
+/// A map of the regions on a disk, where each
+/// used square is labelled with a region id.
+/// Region ids are assigned in row-major order
+/// of the first square found in each region.
+pub struct Regions {
+  labels: Vec<Option<usize>>,
+  sizes: Vec<usize>,
+}
 
-      while let Some((x, y)) = queue.pop_front() {
-        queue.extend(&grid.take(&(x - 1, y)));
-        queue.extend(&grid.take(&(x + 1, y)));
-        queue.extend(&grid.take(&(x, y - 1)));
-        queue.extend(&grid.take(&(x, y + 1)));
+impl Regions {
+  /// The region label for the square at
+  /// column `x` and row `y`, if it is used.
+  pub fn get(&self, x: usize, y: usize) -> Option<usize> {
+    self.labels[y * DISK_SIZE + x]
+  }
+
+  /// The number of regions.
+  pub fn count(&self) -> usize {
+    self.sizes.len()
+  }
+
+  /// The number of squares in each region,
+  /// indexed by region id.
+  pub fn sizes(&self) -> &[usize] {
+    &self.sizes
+  }
+
+  /// The largest region, as `(region, size)`.
+  /// Ties go to the lowest region id.
+  pub fn largest(&self) -> Option<(usize, usize)> {
+    self
+      .sizes
+      .iter()
+      .cloned()
+      .enumerate()
+      .fold(None, |best, (region, size)| match best {
+        Some((_, s)) if s >= size => best,
+        _ => Some((region, size)),
+      })
+  }
+}
+
+impl netpbm::Grid for Disk {
+  type Cell = bool;
+
+  fn width(&self) -> usize {
+    DISK_SIZE
+  }
+
+  fn height(&self) -> usize {
+    DISK_SIZE
+  }
+
+  fn cell(&self, x: usize, y: usize) -> bool {
+    self.get(x, y)
+  }
+}
+
+impl netpbm::Grid for Regions {
+  type Cell = Option<usize>;
+
+  fn width(&self) -> usize {
+    DISK_SIZE
+  }
+
+  fn height(&self) -> usize {
+    DISK_SIZE
+  }
+
+  fn cell(&self, x: usize, y: usize) -> Option<usize> {
+    self.get(x, y)
+  }
+}
+
+impl fmt::Display for Regions {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    for row in self.labels.chunks(DISK_SIZE) {
+      for label in row {
+        match *label {
+          Some(region) => write!(
+            f,
+            "{}",
+            REGION_GLYPHS[region % REGION_GLYPHS.len()] as char
+          )?,
+          None => write!(f, ".")?,
+        }
       }
+      writeln!(f)?;
     }
-
-    regions
+    Ok(())
   }
 }
 
@@ -85,4 +210,31 @@ mod tests {
     assert_eq!(disk.regions(), 1242);
   }
 
+  #[test]
+  fn example_get() {
+    let disk = Disk::new("flqrgnkx");
+    let corner = vec![
+      "##.#.#..", ".#.#.#.#", "....#.#.", "#.#.##.#", ".##.#...", "##..#..#", ".#...#..",
+      "##.#.##.",
+    ];
+    for (y, row) in corner.iter().enumerate() {
+      for (x, c) in row.chars().enumerate() {
+        assert_eq!(disk.get(x, y), c == '#', "square ({}, {})", x, y);
+      }
+    }
+  }
+
+  #[test]
+  fn example_labels() {
+    let regions = Disk::new("flqrgnkx").label();
+    assert_eq!(regions.get(0, 0), Some(0));
+    assert_eq!(regions.get(1, 1), Some(0));
+    assert_eq!(regions.get(2, 0), None);
+    assert_eq!(regions.get(3, 0), Some(1));
+    assert_eq!(regions.sizes().iter().sum::<usize>(), 8108);
+
+    let (region, size) = regions.largest().unwrap();
+    assert!(regions.sizes().iter().all(|&s| s <= size));
+    assert_eq!(regions.sizes()[region], size);
+  }
 }
@@ -1,11 +1,55 @@
+use std::thread;
+
 #[allow(non_camel_case_types)]
 type uint = u64;
 
 static DIVISOR: uint = 2147483647;
 
-#[derive(Debug)]
+/// Number of independent lanes used when filling
+/// a batch of values. Each lane advances by `factor^LANES`
+/// so that the lanes don't depend on each other.
+const LANES: usize = 8;
+
+/// Size of the scratch batch used by picky generators.
+const BATCH: usize = 1024;
+
+/// Number of raw values each thread generates at a time
+/// when searching for picky values in parallel.
+const CHUNK: usize = 1 << 20;
+
+/// Reduce `x` modulo `DIVISOR`, for any `x < DIVISOR^2`.
+///
+/// `DIVISOR` is the Mersenne prime `2^31 - 1`, so
+/// `2^31 = 1 (mod DIVISOR)`, and the high bits can be folded
+/// onto the low bits instead of dividing.
+fn reduce(x: uint) -> uint {
+  let folded = (x & DIVISOR) + (x >> 31);
+  if folded >= DIVISOR {
+    folded - DIVISOR
+  } else {
+    folded
+  }
+}
+
+/// Compute `base^exp % DIVISOR` by repeated squaring.
+fn mod_pow(base: uint, exp: uint) -> uint {
+  let mut result = 1;
+  let mut base = base % DIVISOR;
+  let mut exp = exp;
+  while exp > 0 {
+    if exp & 1 == 1 {
+      result = reduce(result * base);
+    }
+    base = reduce(base * base);
+    exp >>= 1;
+  }
+  result
+}
+
+#[derive(Debug, Clone)]
 pub struct Generator {
   factor: uint,
+  stride: uint,
   prevvalue: uint,
   picky: Option<uint>,
 }
@@ -31,7 +75,7 @@ impl Iterator for Generator {
 
 impl Generator {
   fn nextval(&mut self) -> Option<uint> {
-    let nextvalue = (self.prevvalue * self.factor) % DIVISOR;
+    let nextvalue = reduce(self.prevvalue * self.factor);
     self.prevvalue = nextvalue;
     Some(nextvalue)
   }
@@ -39,6 +83,7 @@ impl Generator {
   pub fn new(factor: uint, start: uint) -> Generator {
     Generator {
       factor: factor,
+      stride: mod_pow(factor, LANES as uint),
       prevvalue: start,
       picky: None,
     }
@@ -47,6 +92,70 @@ impl Generator {
   pub fn picky(&mut self, value: uint) {
     self.picky = Some(value);
   }
+
+  /// Skip ahead by `n` raw values in O(log n) time.
+  ///
+  /// This ignores the picky filter: it is equivalent to
+  /// generating `n` values from the underlying sequence,
+  /// not `n` values which pass the filter.
+  pub fn skip_ahead(&mut self, n: uint) {
+    self.prevvalue = reduce(self.prevvalue * mod_pow(self.factor, n));
+  }
+
+  /// Fill `values` with the next raw values, ignoring the picky filter.
+  fn fill_raw(&mut self, values: &mut [uint]) {
+    let n = values.len();
+    let mut value = self.prevvalue;
+    for v in values.iter_mut().take(LANES) {
+      value = reduce(value * self.factor);
+      *v = value;
+    }
+
+    if n > LANES {
+      let (head, tail) = values.split_at_mut(LANES);
+      let mut lanes = [0; LANES];
+      lanes.copy_from_slice(head);
+      for chunk in tail.chunks_mut(LANES) {
+        for (lane, v) in lanes.iter_mut().zip(chunk.iter_mut()) {
+          *lane = reduce(*lane * self.stride);
+          *v = *lane;
+        }
+      }
+    }
+
+    if n > 0 {
+      self.prevvalue = values[n - 1];
+    }
+  }
+
+  /// Fill `values` with the next values from this generator.
+  /// This produces the same values as calling `next` repeatedly,
+  /// but works in batches which the compiler can vectorize.
+  pub fn fill(&mut self, values: &mut [uint]) {
+    let p = match self.picky {
+      Some(p) => p,
+      None => {
+        return self.fill_raw(values);
+      }
+    };
+
+    let mut batch = [0; BATCH];
+    let mut filled = 0;
+    while filled < values.len() {
+      self.fill_raw(&mut batch);
+      for &v in batch.iter() {
+        if v % p == 0 {
+          values[filled] = v;
+          filled += 1;
+          if filled == values.len() {
+            // Rewind the state to the last value we used.
+            self.prevvalue = v;
+            return;
+          }
+        }
+      }
+    }
+  }
 }
 
 static FILTER: uint = 0b1111111111111111;
@@ -59,8 +168,127 @@ fn judge(a: uint, b: uint) -> uint {
   }
 }
 
+/// Collect the low bits of the picky values generated from each
+/// chunk of `CHUNK` raw values, in parallel, until at least `n`
+/// values have been found. Chunk `i` starts at raw index `i * CHUNK`.
+fn picky_chunks(gen: &Generator, n: usize, threads: usize) -> Vec<Vec<u16>> {
+  let p = gen.picky.unwrap_or(1);
+  let mut chunks = Vec::new();
+  let mut found = 0;
+  while found < n {
+    let handles: Vec<_> = (chunks.len()..chunks.len() + threads)
+      .map(|chunk| {
+        let mut g = gen.clone();
+        g.skip_ahead((chunk * CHUNK) as uint);
+        thread::spawn(move || {
+          let mut batch = [0; BATCH];
+          let mut values = Vec::with_capacity(CHUNK / p as usize);
+          for _ in 0..(CHUNK / BATCH) {
+            g.fill_raw(&mut batch);
+            values.extend(batch.iter().filter(|&v| v % p == 0).map(|&v| v as u16));
+          }
+          values
+        })
+      })
+      .collect();
+
+    for handle in handles {
+      let values = handle.join().expect("Generator thread panicked");
+      found += values.len();
+      chunks.push(values);
+    }
+  }
+  chunks
+}
+
+/// Advance a generator past its first `n` values, using the
+/// chunk counts from `picky_chunks` to jump most of the way.
+fn advance_picky(gen: &mut Generator, chunks: &[Vec<u16>], n: usize) {
+  let mut remaining = n;
+  for (i, chunk) in chunks.iter().enumerate() {
+    if remaining <= chunk.len() {
+      gen.skip_ahead((i * CHUNK) as uint);
+      for _ in 0..remaining {
+        gen.next();
+      }
+      return;
+    }
+    remaining -= chunk.len();
+  }
+}
+
+/// Judge the first `n` pairs from two generators across `threads`
+/// threads. The result, and the final state of the generators, are
+/// the same as for `compete`.
+///
+/// Plain generators are split into ranges of pairs, with each thread
+/// jumping ahead to the start of its range. Picky generators can't
+/// jump to the `i`th accepted value, so instead each thread scans a
+/// range of raw indices, and the accepted values are stitched
+/// together in index order before judging.
+pub fn compete_parallel(a: &mut Generator, b: &mut Generator, n: usize, threads: usize) -> uint {
+  let threads = if threads == 0 { 1 } else { threads };
+
+  if a.picky.is_some() || b.picky.is_some() {
+    let achunks = picky_chunks(a, n, threads);
+    let bchunks = picky_chunks(b, n, threads);
+    let score = achunks
+      .iter()
+      .flat_map(|c| c.iter())
+      .zip(bchunks.iter().flat_map(|c| c.iter()))
+      .take(n)
+      .filter(|&(av, bv)| av == bv)
+      .count();
+    advance_picky(a, &achunks, n);
+    advance_picky(b, &bchunks, n);
+    return score as uint;
+  }
+
+  let per_thread = (n + threads - 1) / threads;
+  let handles: Vec<_> = (0..threads)
+    .map(|t| {
+      let start = t * per_thread;
+      let end = if start + per_thread < n { start + per_thread } else { n };
+      let mut ta = a.clone();
+      let mut tb = b.clone();
+      ta.skip_ahead(start as uint);
+      tb.skip_ahead(start as uint);
+      thread::spawn(move || {
+        if start < end {
+          compete(&mut ta, &mut tb, end - start)
+        } else {
+          0
+        }
+      })
+    })
+    .collect();
+
+  let score = handles
+    .into_iter()
+    .map(|handle| handle.join().expect("Judging thread panicked"))
+    .sum();
+  a.skip_ahead(n as uint);
+  b.skip_ahead(n as uint);
+  score
+}
+
 pub fn compete(a: &mut Generator, b: &mut Generator, n: usize) -> uint {
-  a.zip(b).map(|(av, bv)| judge(av, bv)).take(n).sum()
+  let mut avalues = [0; BATCH];
+  let mut bvalues = [0; BATCH];
+  let mut remaining = n;
+  let mut score = 0;
+  while remaining > 0 {
+    let k = if remaining < BATCH { remaining } else { BATCH };
+    a.fill(&mut avalues[..k]);
+    b.fill(&mut bvalues[..k]);
+    score += avalues[..k]
+      .iter()
+      .zip(bvalues[..k].iter())
+      .map(|(&av, &bv)| judge(av, bv))
+      .sum::<uint>();
+    remaining -= k;
+  }
+  score
 }
 
 #[cfg(test)]
@@ -81,6 +309,51 @@ mod tests {
     );
   }
 
+  #[test]
+  fn mersenne_reduce() {
+    for &x in &[0, 1, DIVISOR - 1, DIVISOR, DIVISOR + 1, 1 << 31, 1 << 40, 123456789 * 987654321] {
+      assert_eq!(reduce(x), x % DIVISOR, "reducing {}", x);
+    }
+    assert_eq!(reduce((DIVISOR - 1) * (DIVISOR - 1)), 1);
+  }
+
+  #[test]
+  fn modular_power() {
+    let mut expected = 1;
+    for exp in 0..100 {
+      assert_eq!(mod_pow(16807, exp), expected);
+      expected = expected * 16807 % DIVISOR;
+    }
+  }
+
+  #[test]
+  fn skip_ahead() {
+    let mut a = Generator::new(16807, 65);
+    a.skip_ahead(4);
+    assert_eq!(a.next(), Some(1352636452));
+
+    let mut b = Generator::new(48271, 8921);
+    let expected = Generator::new(48271, 8921).nth(99999);
+    b.skip_ahead(99999);
+    assert_eq!(b.next(), expected);
+  }
+
+  #[test]
+  fn fill_matches_next() {
+    for &picky in &[None, Some(4)] {
+      let mut a = Generator::new(16807, 65);
+      let mut b = Generator::new(16807, 65);
+      a.picky = picky;
+      b.picky = picky;
+
+      let mut values = vec![0; 3 * BATCH + 5];
+      a.fill(&mut values[..3]);
+      a.fill(&mut values[3..]);
+      assert_eq!(values, b.by_ref().take(3 * BATCH + 5).collect::<Vec<uint>>());
+      assert_eq!(a.next(), b.next());
+    }
+  }
+
   #[test]
   fn example_part1_first_few_score() {
     let mut a = Generator::new(16807, 65);
@@ -95,6 +368,44 @@ mod tests {
     assert_eq!(compete(&mut a, &mut b, 40e+6 as usize), 588);
   }
 
+  #[test]
+  fn parallel_matches_sequential() {
+    for &threads in &[1, 3, 4] {
+      let mut a = Generator::new(16807, 65);
+      let mut b = Generator::new(48271, 8921);
+      let mut pa = Generator::new(16807, 65);
+      let mut pb = Generator::new(48271, 8921);
+      assert_eq!(
+        compete_parallel(&mut pa, &mut pb, 100001, threads),
+        compete(&mut a, &mut b, 100001)
+      );
+      assert_eq!(pa.next(), a.next());
+      assert_eq!(pb.next(), b.next());
+    }
+  }
+
+  #[test]
+  fn parallel_matches_sequential_picky() {
+    for &threads in &[1, 3] {
+      let mut a = Generator::new(16807, 65);
+      let mut b = Generator::new(48271, 8921);
+      let mut pa = Generator::new(16807, 65);
+      let mut pb = Generator::new(48271, 8921);
+      for g in vec![&mut a, &mut pa] {
+        g.picky(4);
+      }
+      for g in vec![&mut b, &mut pb] {
+        g.picky(8);
+      }
+      assert_eq!(
+        compete_parallel(&mut pa, &mut pb, 300001, threads),
+        compete(&mut a, &mut b, 300001)
+      );
+      assert_eq!(pa.next(), a.next());
+      assert_eq!(pb.next(), b.next());
+    }
+  }
+
   #[test]
   fn example_part2_first_few() {
     let mut a = Generator::new(16807, 65);
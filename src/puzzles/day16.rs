@@ -1,54 +1,498 @@
+use super::super::cycle;
 use std::collections::HashMap;
+use std::fmt;
+use std::hash::Hash;
+use std::str::FromStr;
+
+#[derive(Debug, Fail)]
+pub enum DanceError {
+  #[fail(display = "invalid dance move \"{}\"", step)] InvalidMove {
+    step: String,
+  },
+
+  #[fail(display = "position {} is outside a line-up of {} dancers", position, size)]
+  PositionOutOfRange {
+    position: usize,
+    size: usize,
+  },
+
+  #[fail(display = "no dancer named {} in the line-up", name)] UnknownDancer {
+    name: String,
+  },
+
+  #[fail(display = "more than one dancer named {} in the line-up", name)] DuplicateDancer {
+    name: String,
+  },
+
+  #[fail(display = "a dance for {} dancers can't be performed by {}", expected, size)]
+  SizeMismatch {
+    expected: usize,
+    size: usize,
+  },
+
+  #[fail(display = "{} dancers can't be named with single letters", size)] TooManyLetters {
+    size: usize,
+  },
+}
+
+type Result<T> = ::std::result::Result<T, DanceError>;
+
+/// A single dance move, for dancers named by `T`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Move<T = char> {
+  /// Move `n` dancers from the end to the front, keeping their order.
+  Spin(usize),
+
+  /// Swap the dancers at two positions.
+  Exchange(usize, usize),
+
+  /// Swap the dancers with two names.
+  Partner(T, T),
+}
+
+impl<T> FromStr for Move<T>
+where
+  T: FromStr,
+{
+  type Err = DanceError;
+
+  fn from_str(s: &str) -> Result<Self> {
+    let step = s.trim();
+    let invalid = || DanceError::InvalidMove {
+      step: step.to_string(),
+    };
+
+    let mut chars = step.chars();
+    let command = chars.next().ok_or_else(&invalid)?;
+    let operands = chars.as_str();
+    let pair = || -> Result<(&str, &str)> {
+      let mut parts = operands.split('/');
+      match (parts.next(), parts.next(), parts.next()) {
+        (Some(a), Some(b), None) => Ok((a, b)),
+        _ => Err(invalid()),
+      }
+    };
+
+    match command {
+      's' => Ok(Move::Spin(operands.parse().map_err(|_| invalid())?)),
+      'x' => {
+        let (a, b) = pair()?;
+        Ok(Move::Exchange(
+          a.parse().map_err(|_| invalid())?,
+          b.parse().map_err(|_| invalid())?,
+        ))
+      }
+      'p' => {
+        let (a, b) = pair()?;
+        Ok(Move::Partner(
+          a.parse().map_err(|_| invalid())?,
+          b.parse().map_err(|_| invalid())?,
+        ))
+      }
+      _ => Err(invalid()),
+    }
+  }
+}
+
+impl<T> Move<T>
+where
+  T: PartialEq,
+{
+  /// Perform this move in place.
+  ///
+  /// This panics if an operand isn't in the line-up.
+  /// Use a `LineUp` to have moves checked.
+  pub fn apply(&self, dancers: &mut [T]) {
+    match *self {
+      Move::Spin(n) => dance_spin(dancers, n),
+      Move::Exchange(a, b) => dancers.swap(a, b),
+      Move::Partner(ref a, ref b) => dance_partner(dancers, a, b),
+    }
+  }
+}
+
+/// Parse a comma separated list of dance moves.
+pub fn parse_dance<T>(s: &str) -> Result<Vec<Move<T>>>
+where
+  T: FromStr,
+{
+  s.trim().split(',').map(|step| step.parse()).collect()
+}
 
 /// Perform a dance move in place.
 pub fn dance_move(dancers: &mut [char], command: &str) {
-  match command.chars().nth(0).unwrap() {
-    's' => dance_spin(dancers, command),
-    'x' => dance_exchange(dancers, command),
-    'p' => dance_partner(dancers, command),
-    _ => panic!("I don't know this command!"),
-  }
+  let step: Move = command.parse().expect("I don't know this command!");
+  step.apply(dancers);
 }
 
 /// Perform the spin move, rotating n positions.
-fn dance_spin(dancers: &mut [char], command: &str) {
-  let spin = dancers.len() - command[1..].parse::<usize>().unwrap() % dancers.len();
+fn dance_spin<T>(dancers: &mut [T], n: usize) {
+  if dancers.is_empty() {
+    return;
+  }
+  let spin = dancers.len() - n % dancers.len();
   dancers.rotate(spin);
 }
 
-/// Perform the exchange move, switching two indicies.
-fn dance_exchange(dancers: &mut [char], command: &str) {
-  let mut moves = command[1..]
-    .split('/')
-    .map(|x| x.trim().parse::<usize>().unwrap());
-  let a = moves.next().unwrap();
-  let b = moves.next().unwrap();
-  dancers.swap(a, b);
-}
-
 /// Perform the partner move, switching two labels.
-fn dance_partner(dancers: &mut [char], command: &str) {
-  let mut partners = command[1..]
-    .split('/')
-    .map(|x| x.trim().parse::<char>().unwrap());
-  let a = partners.next().unwrap();
-  let b = partners.next().unwrap();
-
-  let ia = dancers
-    .iter()
-    .enumerate()
-    .find(|&(_i, x)| *x == a)
-    .unwrap()
-    .0;
-  let ib = dancers
-    .iter()
-    .enumerate()
-    .find(|&(_i, x)| *x == b)
-    .unwrap()
-    .0;
+fn dance_partner<T>(dancers: &mut [T], a: &T, b: &T)
+where
+  T: PartialEq,
+{
+  let ia = dancers.iter().position(|x| x == a).unwrap();
+  let ib = dancers.iter().position(|x| x == b).unwrap();
   dancers.swap(ia, ib);
 }
 
+/// A line of uniquely named dancers.
+///
+/// Dancers are stored by their index in the original line-up,
+/// along with the position of each dancer, so that every move is
+/// checked against the line-up and partner moves don't have to
+/// search for the dancers.
+#[derive(Debug, Clone)]
+pub struct LineUp<T> {
+  names: Vec<T>,
+  ids: HashMap<T, usize>,
+  order: Vec<usize>,
+  positions: Vec<usize>,
+}
+
+impl LineUp<char> {
+  /// A line-up of `size` dancers, named with consecutive letters
+  /// from `a`. There are only enough letters for 26 dancers; larger
+  /// line-ups can be made with `new` and any other names.
+  pub fn letters(size: usize) -> Result<Self> {
+    if size > 26 {
+      return Err(DanceError::TooManyLetters { size });
+    }
+    LineUp::new((0..size).map(|i| (b'a' + i as u8) as char).collect())
+  }
+}
+
+impl<T> LineUp<T>
+where
+  T: Eq + Hash + Clone + fmt::Debug,
+{
+  /// Create a line-up from dancer names, in order.
+  pub fn new(names: Vec<T>) -> Result<Self> {
+    let mut ids = HashMap::with_capacity(names.len());
+    for (i, name) in names.iter().enumerate() {
+      if ids.insert(name.clone(), i).is_some() {
+        return Err(DanceError::DuplicateDancer {
+          name: format!("{:?}", name),
+        });
+      }
+    }
+    Ok(LineUp {
+      ids,
+      order: (0..names.len()).collect(),
+      positions: (0..names.len()).collect(),
+      names,
+    })
+  }
+
+  /// The number of dancers.
+  pub fn len(&self) -> usize {
+    self.names.len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.names.is_empty()
+  }
+
+  /// Iterate over the dancers, in their current order.
+  pub fn iter<'a>(&'a self) -> LineUpIterator<'a, T> {
+    LineUpIterator {
+      lineup: self,
+      position: 0,
+    }
+  }
+
+  /// The current position of a dancer.
+  pub fn position(&self, name: &T) -> Option<usize> {
+    self.ids.get(name).map(|&id| self.positions[id])
+  }
+
+  fn id(&self, name: &T) -> Result<usize> {
+    self
+      .ids
+      .get(name)
+      .cloned()
+      .ok_or_else(|| DanceError::UnknownDancer {
+        name: format!("{:?}", name),
+      })
+  }
+
+  fn check_position(&self, position: usize) -> Result<usize> {
+    if position < self.len() {
+      Ok(position)
+    } else {
+      Err(DanceError::PositionOutOfRange {
+        position,
+        size: self.len(),
+      })
+    }
+  }
+
+  /// Check that a move can be performed by this line-up.
+  pub fn validate(&self, step: &Move<T>) -> Result<()> {
+    match *step {
+      Move::Spin(_) => {}
+      Move::Exchange(a, b) => {
+        self.check_position(a)?;
+        self.check_position(b)?;
+      }
+      Move::Partner(ref a, ref b) => {
+        self.id(a)?;
+        self.id(b)?;
+      }
+    }
+    Ok(())
+  }
+
+  /// Perform a single move.
+  pub fn step(&mut self, step: &Move<T>) -> Result<()> {
+    self.validate(step)?;
+    match *step {
+      Move::Spin(n) => {
+        dance_spin(&mut self.order, n);
+        for (position, &id) in self.order.iter().enumerate() {
+          self.positions[id] = position;
+        }
+      }
+      Move::Exchange(a, b) => {
+        self.order.swap(a, b);
+        self.positions[self.order[a]] = a;
+        self.positions[self.order[b]] = b;
+      }
+      Move::Partner(ref a, ref b) => {
+        let (a, b) = (self.id(a)?, self.id(b)?);
+        self.order.swap(self.positions[a], self.positions[b]);
+        self.positions.swap(a, b);
+      }
+    }
+    Ok(())
+  }
+
+  /// Perform a list of moves. If any move is invalid,
+  /// nobody moves.
+  pub fn dance(&mut self, steps: &[Move<T>]) -> Result<()> {
+    for step in steps {
+      self.validate(step)?;
+    }
+    for step in steps {
+      self.step(step)?;
+    }
+    Ok(())
+  }
+
+  /// Compile a list of moves into a `Dance` for this line-up.
+  /// Partner moves refer to dancers by their names in this line-up.
+  pub fn compile(&self, steps: &[Move<T>]) -> Result<Dance> {
+    let mut dance = Dance::identity(self.len());
+    let mut inverse: Vec<usize> = (0..self.len()).collect();
+    for step in steps {
+      self.validate(step)?;
+      match *step {
+        Move::Spin(n) => dance_spin(&mut dance.positions, n),
+        Move::Exchange(a, b) => dance.positions.swap(a, b),
+        Move::Partner(ref a, ref b) => {
+          let (a, b) = (self.id(a)?, self.id(b)?);
+          dance.labels.swap(inverse[a], inverse[b]);
+          inverse.swap(a, b);
+        }
+      }
+    }
+    Ok(dance)
+  }
+
+  /// Perform a compiled dance.
+  pub fn perform(&mut self, dance: &Dance) -> Result<()> {
+    if dance.len() != self.len() {
+      return Err(DanceError::SizeMismatch {
+        expected: dance.len(),
+        size: self.len(),
+      });
+    }
+    self.order = dance
+      .positions
+      .iter()
+      .map(|&i| dance.labels[self.order[i]])
+      .collect();
+    for (position, &id) in self.order.iter().enumerate() {
+      self.positions[id] = position;
+    }
+    Ok(())
+  }
+
+  /// The orbit of each dancer's name under a compiled dance.
+  /// Each orbit lists the names a dancer takes on as the dance
+  /// is repeated, before returning to its original name.
+  pub fn name_orbits(&self, dance: &Dance) -> Vec<Vec<T>> {
+    permutation_cycles(&dance.labels)
+      .into_iter()
+      .map(|cycle| cycle.iter().map(|&id| self.names[id].clone()).collect())
+      .collect()
+  }
+}
+
+/// An iterator over the dancers in a line-up.
+pub struct LineUpIterator<'a, T: 'a> {
+  lineup: &'a LineUp<T>,
+  position: usize,
+}
+
+impl<'a, T> Iterator for LineUpIterator<'a, T> {
+  type Item = &'a T;
+
+  fn next(&mut self) -> Option<&'a T> {
+    let id = *self.lineup.order.get(self.position)?;
+    self.position += 1;
+    Some(&self.lineup.names[id])
+  }
+}
+
+impl<T> fmt::Display for LineUp<T>
+where
+  T: fmt::Display,
+{
+  /// Displays the dancers' names in order,
+  /// without separators.
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    for &id in self.order.iter() {
+      write!(f, "{}", self.names[id])?;
+    }
+    Ok(())
+  }
+}
+
+/// Split a permutation into cycles, where `permutation[i]`
+/// is the element which follows `i` in its cycle.
+fn permutation_cycles(permutation: &[usize]) -> Vec<Vec<usize>> {
+  let mut seen = vec![false; permutation.len()];
+  let mut cycles = Vec::new();
+  for start in 0..permutation.len() {
+    if seen[start] {
+      continue;
+    }
+    let mut cycle = Vec::new();
+    let mut i = start;
+    while !seen[i] {
+      seen[i] = true;
+      cycle.push(i);
+      i = permutation[i];
+    }
+    cycles.push(cycle);
+  }
+  cycles
+}
+
+fn gcd(a: u64, b: u64) -> u64 {
+  if b == 0 {
+    a
+  } else {
+    gcd(b, a % b)
+  }
+}
+
+/// A dance compiled into a pair of permutations.
+///
+/// Spins and exchanges only depend on where dancers are standing,
+/// and partner moves only depend on their names, so the two kinds
+/// of move commute. A whole dance is then a permutation of positions
+/// followed by a renaming of the dancers. Dances can be composed
+/// with `then`, and repeated with `pow` by repeated squaring.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Dance {
+  /// `positions[i]` is the position the dancer ending
+  /// at position `i` started the dance from.
+  positions: Vec<usize>,
+
+  /// `labels[j]` is the name which the dancer
+  /// starting with name `j` ends up with.
+  labels: Vec<usize>,
+}
+
+impl Dance {
+  /// The dance where nobody moves.
+  pub fn identity(size: usize) -> Self {
+    Dance {
+      positions: (0..size).collect(),
+      labels: (0..size).collect(),
+    }
+  }
+
+  /// The number of dancers in this dance.
+  pub fn len(&self) -> usize {
+    self.positions.len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.positions.is_empty()
+  }
+
+  /// The dance made by performing this dance, then `other`.
+  ///
+  /// # Panics
+  ///
+  /// If the two dances are for different numbers of dancers.
+  pub fn then(&self, other: &Dance) -> Self {
+    assert_eq!(
+      self.len(),
+      other.len(),
+      "dances for different numbers of dancers can't be combined"
+    );
+    Dance {
+      positions: other.positions.iter().map(|&i| self.positions[i]).collect(),
+      labels: self.labels.iter().map(|&j| other.labels[j]).collect(),
+    }
+  }
+
+  /// This dance, performed `n` times in a row.
+  pub fn pow(&self, n: u64) -> Self {
+    let mut result = Dance::identity(self.positions.len());
+    let mut square = self.clone();
+    let mut n = n;
+    while n > 0 {
+      if n & 1 == 1 {
+        result = result.then(&square);
+      }
+      square = square.then(&square);
+      n >>= 1;
+    }
+    result
+  }
+
+  /// The orbit of each starting position under this dance.
+  /// Each orbit lists the positions a dancer stands at
+  /// as the dance is repeated, ignoring partner moves.
+  pub fn position_orbits(&self) -> Vec<Vec<usize>> {
+    let mut forward = vec![0; self.len()];
+    for (end, &start) in self.positions.iter().enumerate() {
+      forward[start] = end;
+    }
+    permutation_cycles(&forward)
+  }
+
+  /// The orbit of each name under this dance, by index
+  /// into the line-up the dance was compiled for.
+  pub fn label_orbits(&self) -> Vec<Vec<usize>> {
+    permutation_cycles(&self.labels)
+  }
+
+  /// The number of times this dance must be repeated before
+  /// every line-up returns to its starting order.
+  pub fn period(&self) -> u64 {
+    self
+      .position_orbits()
+      .iter()
+      .chain(self.label_orbits().iter())
+      .map(|orbit| orbit.len() as u64)
+      .fold(1, |acc, n| acc / gcd(acc, n) * n)
+  }
+}
+
 pub fn dance(dancers: &mut [char], steps: &[String]) {
   for step in steps {
     dance_move(dancers, step);
@@ -59,20 +503,26 @@ pub fn dance_string(dancers: &[char]) -> String {
   dancers.iter().cloned().collect::<String>()
 }
 
-pub fn dance_cycle(dancers: &mut [char], steps: &[String]) -> (usize, usize) {
-  let mut seen: HashMap<String, usize> = HashMap::new();
-  let mut dstring = dance_string(dancers);
+/// Find the offset and length of the cycle of line-ups
+/// made by repeating the dance, starting from `dancers`.
+/// The line-up itself is left as it is.
+pub fn dance_cycle_from(dancers: &[char], steps: &[String]) -> (usize, usize) {
+  cycle::brent(&dancers.to_vec(), |dancers| {
+    let mut dancers = dancers.clone();
+    dance(&mut dancers, steps);
+    dancers
+  })
+}
 
-  for i in 0.. {
-    seen.insert(dstring.clone(), i);
+/// Find the offset and length of the cycle of line-ups, as
+/// `dance_cycle_from` does. The dancers are then left where the
+/// line-up first repeats, after `offset + length` dances.
+pub fn dance_cycle(dancers: &mut [char], steps: &[String]) -> (usize, usize) {
+  let (offset, length) = dance_cycle_from(dancers, steps);
+  for _ in 0..offset + length {
     dance(dancers, steps);
-    dstring = dance_string(dancers);
-    if seen.contains_key(&dstring) {
-      let x = *seen.get(&dstring).unwrap();
-      return (x, i - x + 1);
-    }
   }
-  panic!("How did we get here?")
+  (offset, length)
 }
 
 #[cfg(test)]
@@ -99,6 +549,114 @@ mod tests {
     assert_eq!(&s, "baedc");
   }
 
+  #[test]
+  fn parse_moves() {
+    assert_eq!(
+      parse_dance("s1,x3/4,pe/b\n").unwrap(),
+      vec![Move::Spin(1), Move::Exchange(3, 4), Move::Partner('e', 'b')]
+    );
+    assert!("x3".parse::<Move>().is_err());
+    assert!("pe/b/c".parse::<Move>().is_err());
+    assert!("q1".parse::<Move>().is_err());
+    assert!("".parse::<Move>().is_err());
+  }
+
+  #[test]
+  fn line_up_dance() {
+    let steps = parse_dance("s1,x3/4,pe/b").unwrap();
+    let mut lineup = LineUp::letters(5).unwrap();
+    lineup.dance(&steps).unwrap();
+    assert_eq!(lineup.to_string(), "baedc");
+    assert_eq!(lineup.position(&'e'), Some(2));
+  }
+
+  #[test]
+  fn line_up_errors() {
+    let mut lineup = LineUp::letters(5).unwrap();
+    match lineup.step(&Move::Exchange(1, 5)) {
+      Err(DanceError::PositionOutOfRange { position: 5, size: 5 }) => {}
+      other => panic!("Unexpected result {:?}", other),
+    };
+    match lineup.dance(&parse_dance("s1,pa/z").unwrap()) {
+      Err(DanceError::UnknownDancer { .. }) => {}
+      other => panic!("Unexpected result {:?}", other),
+    };
+    assert_eq!(lineup.to_string(), "abcde");
+    assert!(lineup.compile(&[Move::Partner('a', 'q')]).is_err());
+    assert!(LineUp::new(vec!['a', 'b', 'a']).is_err());
+    assert_eq!(LineUp::letters(26).unwrap().iter().last(), Some(&'z'));
+    match LineUp::letters(27) {
+      Err(DanceError::TooManyLetters { size: 27 }) => {}
+      other => panic!("unexpected {:?}", other),
+    }
+  }
+
+  #[test]
+  fn named_dancers() {
+    let names: Vec<String> = vec!["alice", "bob", "carol"]
+      .iter()
+      .map(|x| x.to_string())
+      .collect();
+    let mut lineup = LineUp::new(names).unwrap();
+    let steps: Vec<Move<String>> = parse_dance("s1,pbob/alice").unwrap();
+    let dance = lineup.compile(&steps).unwrap();
+    lineup.perform(&dance).unwrap();
+    assert_eq!(
+      lineup.iter().cloned().collect::<Vec<String>>(),
+      vec!["carol", "bob", "alice"]
+    );
+  }
+
+  #[test]
+  fn compiled_dance() {
+    let steps = parse_dance("s1,x3/4,pe/b").unwrap();
+    let mut lineup = LineUp::letters(5).unwrap();
+    let dance = lineup.compile(&steps).unwrap();
+
+    lineup.perform(&dance).unwrap();
+    assert_eq!(lineup.to_string(), "baedc");
+
+    let mut lineup = LineUp::letters(5).unwrap();
+    lineup.perform(&dance.pow(2)).unwrap();
+    assert_eq!(lineup.to_string(), "ceadb");
+    assert!(LineUp::letters(4).unwrap().perform(&dance).is_err());
+  }
+
+  #[test]
+  fn compiled_dance_power() {
+    let steps = parse_dance("s3,x0/4,pa/c,x1/2,pd/b,s2").unwrap();
+    let dance = LineUp::letters(5).unwrap().compile(&steps).unwrap();
+    let mut programs: Vec<char> = "abcde".chars().collect();
+    for n in 0..20 {
+      let mut powered = LineUp::letters(5).unwrap();
+      powered.perform(&dance.pow(n)).unwrap();
+      assert_eq!(powered.to_string(), dance_string(&programs), "power {}", n);
+      for step in steps.iter() {
+        step.apply(&mut programs);
+      }
+    }
+  }
+
+  #[test]
+  fn dance_orbits() {
+    let steps = parse_dance("s1,x3/4,pe/b").unwrap();
+    let lineup = LineUp::letters(5).unwrap();
+    let dance = lineup.compile(&steps).unwrap();
+    assert_eq!(dance.position_orbits(), vec![vec![0, 1, 2, 4], vec![3]]);
+    assert_eq!(
+      lineup.name_orbits(&dance),
+      vec![vec!['a'], vec!['b', 'e'], vec!['c'], vec!['d']]
+    );
+    assert_eq!(dance.period(), 4);
+    assert_eq!(dance.pow(dance.period()), Dance::identity(5));
+  }
+
+  #[test]
+  #[should_panic]
+  fn combine_mismatched_dances() {
+    Dance::identity(5).then(&Dance::identity(4));
+  }
+
   #[test]
   fn find_cycle() {
     let mut programs: Vec<char> = "abcde".chars().collect();
@@ -121,6 +679,22 @@ mod tests {
     assert_eq!(or, dance_string(&programs))
   }
 
+  #[test]
+  fn find_cycle_from() {
+    let programs: Vec<char> = "abcde".chars().collect();
+    let steps: Vec<String> = vec!["s1", "x3/4", "pe/b"]
+      .iter()
+      .map(|x| x.to_string())
+      .collect();
+    assert_eq!(dance_cycle_from(&programs, &steps), (0, 4));
+    assert_eq!(dance_string(&programs), "abcde");
+
+    let mut danced = programs.clone();
+    dance(&mut danced, &steps);
+    assert_eq!(dance_cycle(&mut danced, &steps), (0, 4));
+    assert_eq!(dance_string(&danced), "baedc");
+  }
+
   use test::Bencher;
   use std::fs::File;
   use std::io::prelude::*;
@@ -1,15 +1,176 @@
-pub fn spinlock(length: usize, skip: usize) -> Vec<usize> {
-  let mut buffer = Vec::with_capacity(length);
-  let mut position = 0;
+//! Day 17: Spinlock
+//!
+//! The spinlock's circular buffer is stored as a ring of
+//! chunks, each a short `Vec`. Inserting only moves the values
+//! in one chunk, and the spinlock only has to walk past whole
+//! chunks to find where the next value goes. Each value also
+//! records which chunk it is in, so finding the value after
+//! any other value only has to search one chunk.
 
-  for i in 1..length {
-    position = (position + skip + 1) % i;
-    buffer.insert(position, i);
+/// The number of values in a chunk after it is split.
+/// Chunks are split when they reach twice this size.
+const CHUNK: usize = 256;
+
+struct Chunk {
+  values: Vec<u32>,
+  next: usize,
+}
+
+/// A spinlock's circular buffer, and its current position.
+pub struct Spinlock {
+  skip: usize,
+  chunks: Vec<Chunk>,
+  chunk_of: Vec<u32>,
+  chunk: usize,
+  offset: usize,
+}
+
+impl Spinlock {
+  /// A new spinlock, holding only the value 0.
+  pub fn new(skip: usize) -> Self {
+    Spinlock {
+      skip,
+      chunks: vec![
+        Chunk {
+          values: vec![0],
+          next: 0,
+        },
+      ],
+      chunk_of: vec![0],
+      chunk: 0,
+      offset: 0,
+    }
   }
 
-  buffer
+  /// The number of values in the buffer. This
+  /// is also the next value to be inserted.
+  pub fn len(&self) -> usize {
+    self.chunk_of.len()
+  }
+
+  /// The buffer always holds at least the value 0.
+  pub fn is_empty(&self) -> bool {
+    false
+  }
+
+  /// The value at the current position.
+  pub fn current(&self) -> usize {
+    self.chunks[self.chunk].values[self.offset] as usize
+  }
+
+  /// Step forward and insert the next value after
+  /// the new position, returning the value inserted.
+  pub fn step(&mut self) -> usize {
+    let mut n = self.skip % self.len();
+    loop {
+      let remaining = self.chunks[self.chunk].values.len() - 1 - self.offset;
+      if n <= remaining {
+        self.offset += n;
+        break;
+      }
+      n -= remaining + 1;
+      self.chunk = self.chunks[self.chunk].next;
+      self.offset = 0;
+    }
+    let (chunk, offset) = (self.chunk, self.offset + 1);
+    self.insert_in(chunk, offset)
+  }
+
+  /// Step until the last value inserted is `value`.
+  pub fn run_to(&mut self, value: usize) {
+    while self.len() <= value {
+      self.step();
+    }
+  }
+
+  /// Insert the next value at `position`, counting from the start
+  /// of the buffer, and make it the current position. Positions
+  /// past the end of the buffer wrap around. Returns the value.
+  pub fn insert(&mut self, position: usize) -> usize {
+    let mut position = position % (self.len() + 1);
+    let mut chunk = 0;
+    while position > self.chunks[chunk].values.len() {
+      position -= self.chunks[chunk].values.len();
+      chunk = self.chunks[chunk].next;
+    }
+    self.insert_in(chunk, position)
+  }
+
+  fn insert_in(&mut self, chunk: usize, offset: usize) -> usize {
+    let value = self.len();
+    self.chunks[chunk].values.insert(offset, value as u32);
+    self.chunk_of.push(chunk as u32);
+    self.chunk = chunk;
+    self.offset = offset;
+    if self.chunks[chunk].values.len() >= 2 * CHUNK {
+      self.split(chunk);
+    }
+    value
+  }
+
+  /// Move the second half of a chunk into a new chunk after it.
+  fn split(&mut self, chunk: usize) {
+    let id = self.chunks.len();
+    let values = self.chunks[chunk].values.split_off(CHUNK);
+    for &value in values.iter() {
+      self.chunk_of[value as usize] = id as u32;
+    }
+    let next = self.chunks[chunk].next;
+    self.chunks[chunk].next = id;
+    self.chunks.push(Chunk { values, next });
+    if self.chunk == chunk && self.offset >= CHUNK {
+      self.chunk = id;
+      self.offset -= CHUNK;
+    }
+  }
+
+  /// The value following `value` in the buffer,
+  /// if `value` has been inserted yet.
+  pub fn value_after(&self, value: usize) -> Option<usize> {
+    let chunk = &self.chunks[*self.chunk_of.get(value)? as usize];
+    let offset = chunk.values.iter().position(|&v| v as usize == value)?;
+    let after = match chunk.values.get(offset + 1) {
+      Some(&after) => after,
+      None => self.chunks[chunk.next].values[0],
+    };
+    Some(after as usize)
+  }
+
+  /// Iterate over the buffer, from the start.
+  pub fn iter<'a>(&'a self) -> SpinlockIterator<'a> {
+    SpinlockIterator {
+      spinlock: self,
+      chunk: Some(0),
+      offset: 0,
+    }
+  }
+}
+
+/// An iterator over the values in a spinlock's buffer.
+pub struct SpinlockIterator<'a> {
+  spinlock: &'a Spinlock,
+  chunk: Option<usize>,
+  offset: usize,
 }
 
+impl<'a> Iterator for SpinlockIterator<'a> {
+  type Item = usize;
+
+  fn next(&mut self) -> Option<usize> {
+    let chunk = &self.spinlock.chunks[self.chunk?];
+    let value = chunk.values[self.offset];
+    self.offset += 1;
+    if self.offset == chunk.values.len() {
+      self.offset = 0;
+      self.chunk = Some(chunk.next).filter(|&c| c != 0);
+    }
+    Some(value as usize)
+  }
+}
+
+/// The value after 0 once `length - 1` values have
+/// been inserted. Only the position of 0 matters, which
+/// never moves, so no buffer is needed.
 pub fn spinlock_afterzero(length: usize, skip: usize) -> usize {
   let mut vaz = 0;
   let mut position = 0;
@@ -23,16 +184,6 @@ pub fn spinlock_afterzero(length: usize, skip: usize) -> usize {
   vaz
 }
 
-pub fn getnextitem(lock: &[usize], target: usize) -> usize {
-  let n = (lock
-    .iter()
-    .enumerate()
-    .find(|&(_i, x)| *x == target)
-    .unwrap()
-    .0 + 1) % lock.len();
-  lock[n]
-}
-
 #[cfg(test)]
 mod test {
 
@@ -40,7 +191,58 @@ mod test {
 
   #[test]
   fn small_lock() {
-    let locked = spinlock(2018, 3);
-    assert_eq!(getnextitem(&locked, 2017), 638);
+    let mut lock = Spinlock::new(3);
+    lock.run_to(2017);
+    assert_eq!(lock.value_after(2017), Some(638));
+  }
+
+  #[test]
+  fn first_steps() {
+    let mut lock = Spinlock::new(3);
+    lock.run_to(9);
+    assert_eq!(
+      lock.iter().collect::<Vec<_>>(),
+      vec![0, 9, 5, 7, 2, 4, 3, 8, 6, 1]
+    );
+    assert_eq!(lock.current(), 9);
+    assert_eq!(lock.value_after(1), Some(0));
+    assert_eq!(lock.value_after(10), None);
+  }
+
+  #[test]
+  fn matches_vec() {
+    let mut lock = Spinlock::new(382);
+    let mut buffer = vec![0];
+    let mut position = 0;
+    for i in 1..5000 {
+      position = (position + 382) % i + 1;
+      buffer.insert(position, i);
+      assert_eq!(lock.step(), i);
+    }
+    assert!(lock.iter().eq(buffer.iter().cloned()));
+    for (i, &value) in buffer.iter().enumerate() {
+      assert_eq!(lock.value_after(value), Some(buffer[(i + 1) % buffer.len()]));
+    }
+  }
+
+  #[test]
+  fn insert_anywhere() {
+    let mut lock = Spinlock::new(0);
+    lock.insert(1);
+    lock.insert(0);
+    lock.insert(2);
+    assert_eq!(lock.iter().collect::<Vec<_>>(), vec![2, 0, 3, 1]);
+    assert_eq!(lock.current(), 3);
+    assert_eq!(lock.value_after(1), Some(2));
+  }
+
+  #[test]
+  fn after_zero() {
+    let mut lock = Spinlock::new(382);
+    lock.run_to(100_000);
+    assert_eq!(
+      lock.value_after(0),
+      Some(spinlock_afterzero(100_001, 382))
+    );
   }
 }
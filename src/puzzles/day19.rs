@@ -1,64 +1,110 @@
-use std::collections::HashMap;
-use std::io;
+//! Day 19: A Series of Tubes
+//!
+//! The network is a diagram of tubes, entered from the top
+//! edge. Packets go straight through tubes and letters, even
+//! where tubes cross, and only turn at `+` junctions.
 
+use std::collections::HashSet;
+use std::fmt;
+use std::str::FromStr;
+
+/// A position in the diagram, as `(column, row)`.
 #[derive(Debug, PartialEq, Eq, Copy, Clone, Hash)]
-pub struct Point(isize, isize);
+pub struct Point(pub isize, pub isize);
 
-type NetworkMap = HashMap<Point, char>;
+impl fmt::Display for Point {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "({}, {})", self.0, self.1)
+  }
+}
 
-pub fn create_map<T: io::BufRead>(lines: io::Lines<T>) -> NetworkMap {
-  let mut map = NetworkMap::new();
+#[derive(Debug, Fail, Clone, PartialEq, Eq)]
+pub enum MapError {
+  #[fail(display = "unexpected '{}' at {}", found, position)]
+  UnknownCell { position: Point, found: char },
 
-  for (i, line) in lines.enumerate() {
-    for (j, c) in line.expect("Readable line!").chars().enumerate() {
-      match c {
-        ' ' => {} // Skip spaces, all others matter.
-        _ => {
-          map.insert(Point(j as isize, i as isize), c);
-        }
-      }
+  #[fail(display = "no entry point on the top row")] NoEntry,
+
+  #[fail(display = "entry points at both {} and {}", first, second)]
+  MultipleEntries { first: Point, second: Point },
+
+  #[fail(display = "path ends at {} without reaching a letter", position)]
+  DeadEnd { position: Point },
+
+  #[fail(display = "junction at {} could turn either way", position)]
+  AmbiguousJunction { position: Point },
+
+  #[fail(display = "path loops back to {}", position)]
+  Loop { position: Point },
+}
+
+impl MapError {
+  /// Where in the diagram the problem is, if anywhere.
+  pub fn position(&self) -> Option<Point> {
+    match *self {
+      MapError::UnknownCell { position, .. }
+      | MapError::DeadEnd { position }
+      | MapError::AmbiguousJunction { position }
+      | MapError::Loop { position } => Some(position),
+      MapError::MultipleEntries { second, .. } => Some(second),
+      MapError::NoEntry => None,
     }
   }
-  map
 }
 
-pub trait Traverseable {
-  fn start(&self) -> Option<Point>;
-  fn traverse<'a>(&'a self) -> NetworkPathIterator<'a>;
-  fn traverse_letters<'a>(&'a self) -> NetworkPathIterator<'a>;
+type Result<T> = ::std::result::Result<T, MapError>;
+
+/// A single square in the diagram.
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum Cell {
+  Empty,
+
+  /// A `|` tube.
+  Vertical,
+
+  /// A `-` tube.
+  Horizontal,
+
+  /// A `+`, where the path turns.
+  Junction,
+
+  Letter(char),
 }
 
-impl Traverseable for NetworkMap {
-  fn start(&self) -> Option<Point> {
-    for (point, _c) in self {
-      if point.1 == 0 {
-        return Some(*point);
-      }
+impl Cell {
+  fn parse(c: char) -> Option<Cell> {
+    match c {
+      ' ' => Some(Cell::Empty),
+      '|' => Some(Cell::Vertical),
+      '-' => Some(Cell::Horizontal),
+      '+' => Some(Cell::Junction),
+      c if c.is_ascii_alphabetic() => Some(Cell::Letter(c)),
+      _ => None,
     }
-    None
   }
 
-  fn traverse<'a>(&'a self) -> NetworkPathIterator<'a> {
-    NetworkPathIterator {
-      position: self.start().expect("Can't locate the start of the graph!"),
-      direction: Direction::Up,
-      map: &self,
-      letters: false,
-    }
+  pub fn is_empty(&self) -> bool {
+    *self == Cell::Empty
   }
+}
 
-  fn traverse_letters<'a>(&'a self) -> NetworkPathIterator<'a> {
-    NetworkPathIterator {
-      position: self.start().expect("Can't locate the start of the graph!"),
-      direction: Direction::Up,
-      map: &self,
-      letters: true,
-    }
+impl fmt::Display for Cell {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    let c = match *self {
+      Cell::Empty => ' ',
+      Cell::Vertical => '|',
+      Cell::Horizontal => '-',
+      Cell::Junction => '+',
+      Cell::Letter(c) => c,
+    };
+    write!(f, "{}", c)
   }
 }
 
+/// The direction a packet is travelling in, as
+/// seen on the diagram: `Down` increases the row.
 #[derive(Debug, PartialEq, Eq, Copy, Clone, Hash)]
-enum Direction {
+pub enum Direction {
   Up,
   Down,
   Left,
@@ -73,89 +119,289 @@ impl Direction {
     }
   }
 
-  fn progress(&self, point: &Point) -> Point {
+  fn progress(&self, point: Point) -> Point {
     match *self {
-      Direction::Up => Point(point.0, point.1 + 1),
-      Direction::Down => Point(point.0, point.1 - 1),
+      Direction::Up => Point(point.0, point.1 - 1),
+      Direction::Down => Point(point.0, point.1 + 1),
       Direction::Left => Point(point.0 - 1, point.1),
       Direction::Right => Point(point.0 + 1, point.1),
     }
   }
+}
 
-  fn turn(&self, point: &Point, map: &NetworkMap) -> Result<Direction, String> {
-    match map.contains_key(&self.progress(point)) {
-      true => Ok(*self),
-      false => {
-        let (l, r) = self.turns();
-        if map.contains_key(&l.progress(point)) {
-          Ok(l)
-        } else if map.contains_key(&r.progress(point)) {
-          Ok(r)
-        } else {
-          Err("No valid directions...".to_string())
-        }
+/// A network diagram, stored as a dense grid.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Network {
+  cells: Vec<Cell>,
+  width: usize,
+  height: usize,
+}
+
+impl FromStr for Network {
+  type Err = MapError;
+
+  /// Parse a diagram. Lines may have different
+  /// lengths, and are padded with empty cells.
+  fn from_str(s: &str) -> Result<Self> {
+    let lines: Vec<&str> = s.lines().collect();
+    let width = lines.iter().map(|l| l.chars().count()).max().unwrap_or(0);
+    let height = lines.len();
+    let mut cells = vec![Cell::Empty; width * height];
+    for (y, line) in lines.iter().enumerate() {
+      for (x, c) in line.chars().enumerate() {
+        cells[y * width + x] = Cell::parse(c).ok_or(MapError::UnknownCell {
+          position: Point(x as isize, y as isize),
+          found: c,
+        })?;
       }
     }
+    Ok(Network {
+      cells,
+      width,
+      height,
+    })
   }
 }
 
-// Iterator over the characters in a path.
-pub struct NetworkPathIterator<'a> {
-  position: Point,
-  direction: Direction,
-  map: &'a NetworkMap,
-  letters: bool,
-}
+impl Network {
+  pub fn width(&self) -> usize {
+    self.width
+  }
+
+  pub fn height(&self) -> usize {
+    self.height
+  }
 
-impl<'a> NetworkPathIterator<'a> {
-  /// Checks a position for validity, returning the character in
-  /// that position if it is valid.
-  fn checkposition(&mut self, position: &Point) -> Option<char> {
-    match self.map.get(position) {
-      Some(item) => {
-        self.position = *position;
-        Some(*item)
+  /// The cell at a point. Everything
+  /// outside the diagram is empty.
+  pub fn get(&self, point: Point) -> Cell {
+    let Point(x, y) = point;
+    if x < 0 || y < 0 || x as usize >= self.width || y as usize >= self.height {
+      return Cell::Empty;
+    }
+    self.cells[y as usize * self.width + x as usize]
+  }
+
+  /// The single non-empty cell on the top row.
+  pub fn entry(&self) -> Result<Point> {
+    let mut entries = (0..self.width)
+      .map(|x| Point(x as isize, 0))
+      .filter(|&p| !self.get(p).is_empty());
+    let first = entries.next().ok_or(MapError::NoEntry)?;
+    match entries.next() {
+      Some(second) => Err(MapError::MultipleEntries { first, second }),
+      None => Ok(first),
+    }
+  }
+
+  /// Follow the path from the entry point. The path ends at
+  /// a letter with nowhere further to go; anything else that
+  /// stops the packet is reported as an error.
+  pub fn traverse<'a>(&'a self) -> Traversal<'a> {
+    Traversal {
+      network: self,
+      next: Some(self.entry().map(|p| (p, Direction::Down))),
+      visited: HashSet::new(),
+    }
+  }
+
+  /// The letters seen along the path, in order.
+  pub fn letters(&self) -> Result<String> {
+    let mut letters = String::new();
+    for step in self.traverse() {
+      if let Cell::Letter(c) = step?.cell {
+        letters.push(c);
       }
-      None => None,
     }
+    Ok(letters)
+  }
+
+  /// The number of cells the path visits.
+  pub fn length(&self) -> Result<usize> {
+    self.traverse().try_fold(0, |n, step| step.map(|_| n + 1))
   }
 
-  fn nextstep(&mut self) -> Result<char, String> {
-    let position = self.position;
-    let nextposition = self.direction.progress(&position);
-    let nextitem = self.checkposition(&nextposition).map_or_else(
-      || {
-        self.direction = match self.direction.turn(&position, self.map) {
-          Ok(direction) => direction,
-          Err(e) => {
-            return Err(e);
+  /// Follow the path, and draw it over the diagram.
+  pub fn overlay<'a>(&'a self, highlight: Highlight) -> Overlay<'a> {
+    let mut steps = vec![None; self.width * self.height];
+    let mut error = None;
+    for (n, step) in self.traverse().enumerate() {
+      match step {
+        Ok(step) => {
+          let Point(x, y) = step.position;
+          let visited = &mut steps[y as usize * self.width + x as usize];
+          if visited.is_none() {
+            *visited = Some(n);
           }
+        }
+        Err(e) => error = Some(e),
+      }
+    }
+    Overlay {
+      network: self,
+      steps,
+      error,
+      highlight,
+    }
+  }
+}
+
+/// How an `Overlay` shows the path.
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum Highlight {
+  /// Colour the path with ANSI escape codes.
+  Ansi,
+
+  /// Replace the tubes on the path with `*`,
+  /// and mark the cell where an error happened with `!`.
+  Markers,
+}
+
+const ANSI_PATH: &str = "\x1b[32m";
+const ANSI_LETTER: &str = "\x1b[1;33m";
+const ANSI_ERROR: &str = "\x1b[1;41m";
+const ANSI_RESET: &str = "\x1b[0m";
+
+/// A network diagram with the path taken through it highlighted.
+/// Each row is followed by the step number of each letter in
+/// the row, counting the entry point as step 0. If the path
+/// couldn't be followed, the error is shown on the last line.
+pub struct Overlay<'a> {
+  network: &'a Network,
+  steps: Vec<Option<usize>>,
+  error: Option<MapError>,
+  highlight: Highlight,
+}
+
+impl<'a> Overlay<'a> {
+  /// The step at which a point was first visited.
+  pub fn step(&self, point: Point) -> Option<usize> {
+    let Point(x, y) = point;
+    if self.network.get(point).is_empty() {
+      return None;
+    }
+    self.steps[y as usize * self.network.width + x as usize]
+  }
+
+  /// The error which stopped the packet, if any.
+  pub fn error(&self) -> Option<&MapError> {
+    self.error.as_ref()
+  }
+
+  fn write_cell(&self, f: &mut fmt::Formatter, point: Point, failed: bool) -> fmt::Result {
+    let cell = self.network.get(point);
+    let visited = self.step(point).is_some();
+    match self.highlight {
+      Highlight::Ansi => {
+        let color = match cell {
+          _ if failed => ANSI_ERROR,
+          Cell::Letter(_) if visited => ANSI_LETTER,
+          _ if visited => ANSI_PATH,
+          _ => return write!(f, "{}", cell),
         };
-        let nextposition = self.direction.progress(&position);
-        self
-          .checkposition(&nextposition)
-          .ok_or("No possible paths".to_string())
+        write!(f, "{}{}{}", color, cell, ANSI_RESET)
+      }
+      Highlight::Markers => match cell {
+        _ if failed => write!(f, "!"),
+        Cell::Letter(_) | Cell::Empty => write!(f, "{}", cell),
+        _ if visited => write!(f, "*"),
+        _ => write!(f, "{}", cell),
       },
-      |x| Ok(x),
-    );
-    nextitem
+    }
   }
 }
 
-impl<'a> Iterator for NetworkPathIterator<'a> {
-  type Item = char;
+impl<'a> fmt::Display for Overlay<'a> {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    let failed = self.error.as_ref().and_then(|e| e.position());
+    for y in 0..self.network.height as isize {
+      let mut letters = Vec::new();
+      for x in 0..self.network.width as isize {
+        let point = Point(x, y);
+        self.write_cell(f, point, failed == Some(point))?;
+        if let (Cell::Letter(c), Some(n)) = (self.network.get(point), self.step(point)) {
+          letters.push(format!("{}:{}", c, n));
+        }
+      }
+      if !letters.is_empty() {
+        write!(f, "  {}", letters.join(" "))?;
+      }
+      writeln!(f)?;
+    }
+    if let Some(ref e) = self.error {
+      writeln!(f, "error: {}", e)?;
+    }
+    Ok(())
+  }
+}
 
-  fn next(&mut self) -> Option<char> {
-    loop {
-      match self.nextstep() {
-        Ok(c) => {
-          if !self.letters || c.is_alphabetic() {
-            return Some(c);
-          }
+/// A cell visited by the packet, and
+/// the direction it entered the cell in.
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub struct Step {
+  pub position: Point,
+  pub direction: Direction,
+  pub cell: Cell,
+}
+
+/// An iterator over the steps along the path through a network.
+/// If the network is malformed, the last item is an error. A path
+/// which enters a cell in the same direction twice never ends, so
+/// it is reported as a loop.
+pub struct Traversal<'a> {
+  network: &'a Network,
+  next: Option<Result<(Point, Direction)>>,
+  visited: HashSet<(Point, Direction)>,
+}
+
+impl<'a> Traversal<'a> {
+  /// Where the packet goes after a step, if anywhere.
+  fn follow(&self, step: &Step) -> Option<Result<(Point, Direction)>> {
+    let open = |d: Direction| !self.network.get(d.progress(step.position)).is_empty();
+    match step.cell {
+      Cell::Junction => {
+        let (left, right) = step.direction.turns();
+        match (open(left), open(right)) {
+          (true, true) => Some(Err(MapError::AmbiguousJunction {
+            position: step.position,
+          })),
+          (true, false) => Some(Ok((left.progress(step.position), left))),
+          (false, true) => Some(Ok((right.progress(step.position), right))),
+          (false, false) => Some(Err(MapError::DeadEnd {
+            position: step.position,
+          })),
         }
-        Err(_) => return None,
       }
+      _ if open(step.direction) => Some(Ok((
+        step.direction.progress(step.position),
+        step.direction,
+      ))),
+      Cell::Letter(_) => None,
+      _ => Some(Err(MapError::DeadEnd {
+        position: step.position,
+      })),
+    }
+  }
+}
+
+impl<'a> Iterator for Traversal<'a> {
+  type Item = Result<Step>;
+
+  fn next(&mut self) -> Option<Result<Step>> {
+    let (position, direction) = match self.next.take()? {
+      Ok(next) => next,
+      Err(e) => return Some(Err(e)),
+    };
+    if !self.visited.insert((position, direction)) {
+      return Some(Err(MapError::Loop { position }));
     }
+    let step = Step {
+      position,
+      direction,
+      cell: self.network.get(position),
+    };
+    self.next = self.follow(&step);
+    Some(Ok(step))
   }
 }
 
@@ -163,48 +409,144 @@ impl<'a> Iterator for NetworkPathIterator<'a> {
 mod test {
 
   use super::*;
-  use std::io::BufRead;
+
+  static EXAMPLE: &str = "     |
+     |  +--+
+     A  |  C
+ F---|----E|--+
+     |  |  |  D
+     +B-+  +--+
+";
 
   #[test]
   fn parse_test() {
-    #[cfg_attr(rustfmt, rustfmt_skip)]
-    let mapdata = "     |          
-     |  +--+    
-     A  |  C    
- F---|----E|--+ 
-     |  |  |  D 
-     +B-+  +--+ 
-";
-    let map = create_map(mapdata.as_bytes().lines());
-    assert_eq!(map.len(), 35);
+    let network: Network = EXAMPLE.parse().unwrap();
+    assert_eq!(network.width(), 15);
+    assert_eq!(network.height(), 6);
+    assert_eq!(network.get(Point(5, 2)), Cell::Letter('A'));
+    assert_eq!(network.get(Point(8, 1)), Cell::Junction);
+    assert_eq!(network.get(Point(-1, 0)), Cell::Empty);
+    assert_eq!(network.entry(), Ok(Point(5, 0)));
   }
 
   #[test]
   fn traverse_letters_test() {
-    #[cfg_attr(rustfmt, rustfmt_skip)]
-    let mapdata = "     |          
-     |  +--+    
-     A  |  C    
- F---|----E|--+ 
-     |  |  |  D 
-     +B-+  +--+ 
-";
-    let map = create_map(mapdata.as_bytes().lines());
-    let seen_letters: String = map.traverse_letters().collect();
-    assert_eq!(&seen_letters, "ABCDEF")
+    let network: Network = EXAMPLE.parse().unwrap();
+    assert_eq!(network.letters(), Ok("ABCDEF".to_string()));
   }
 
   #[test]
   fn traverse_path_length_test() {
-    #[cfg_attr(rustfmt, rustfmt_skip)]
-    let mapdata = "     |          
-     |  +--+    
-     A  |  C    
- F---|----E|--+ 
-     |  |  |  D 
-     +B-+  +--+ 
-";
-    let map = create_map(mapdata.as_bytes().lines());
-    assert_eq!(map.traverse().count() + 1, 38);
+    let network: Network = EXAMPLE.parse().unwrap();
+    assert_eq!(network.length(), Ok(38));
+  }
+
+  #[test]
+  fn traverse_steps() {
+    let network: Network = EXAMPLE.parse().unwrap();
+    let steps: Vec<Step> = network.traverse().map(|s| s.unwrap()).collect();
+    assert_eq!(
+      steps[5],
+      Step {
+        position: Point(5, 5),
+        direction: Direction::Down,
+        cell: Cell::Junction,
+      }
+    );
+    assert_eq!(steps[6].direction, Direction::Right);
+    assert_eq!(steps[37].cell, Cell::Letter('F'));
+  }
+
+  #[test]
+  fn overlay_markers() {
+    let network: Network = EXAMPLE.parse().unwrap();
+    let overlay = network.overlay(Highlight::Markers);
+    assert_eq!(overlay.step(Point(5, 2)), Some(2));
+    assert_eq!(overlay.step(Point(5, 3)), Some(3));
+    assert_eq!(overlay.step(Point(0, 0)), None);
+    assert_eq!(
+      overlay.to_string(),
+      "     *         \n     *  ****   \n     A  *  C     A:2 C:16\n \
+       F********E****  F:37 E:28\n     *  *  *  D  D:23\n     *B**  ****  B:6\n"
+    );
+  }
+
+  #[test]
+  fn overlay_error() {
+    let network: Network = " |\n A\n +--\n".parse().unwrap();
+    let overlay = network.overlay(Highlight::Markers);
+    assert_eq!(
+      overlay.to_string(),
+      " *  \n A    A:1\n **!\nerror: path ends at (3, 2) without reaching a letter\n"
+    );
+
+    let overlay = network.overlay(Highlight::Ansi);
+    assert!(overlay.to_string().contains("\x1b[1;33mA\x1b[0m"));
+    assert!(overlay.to_string().contains("\x1b[1;41m-\x1b[0m"));
+  }
+
+  #[test]
+  fn unknown_cell() {
+    assert_eq!(
+      " |\n *\n".parse::<Network>(),
+      Err(MapError::UnknownCell {
+        position: Point(1, 1),
+        found: '*',
+      })
+    );
+  }
+
+  #[test]
+  fn bad_entries() {
+    let network: Network = "\n |\n".parse().unwrap();
+    assert_eq!(network.length(), Err(MapError::NoEntry));
+
+    let network: Network = " | |\n | |\n".parse().unwrap();
+    assert_eq!(
+      network.length(),
+      Err(MapError::MultipleEntries {
+        first: Point(1, 0),
+        second: Point(3, 0),
+      })
+    );
+  }
+
+  #[test]
+  fn dead_end() {
+    let network: Network = " |\n A\n +--\n".parse().unwrap();
+    assert_eq!(
+      network.letters(),
+      Err(MapError::DeadEnd {
+        position: Point(3, 2),
+      })
+    );
+    let steps: Vec<_> = network.traverse().collect();
+    assert_eq!(steps.len(), 6);
+    assert!(steps[4].is_ok());
+  }
+
+  #[test]
+  fn closed_loop() {
+    let network: Network = " |\n +-+\n | |\n +-+\n".parse().unwrap();
+    assert_eq!(
+      network.length(),
+      Err(MapError::Loop {
+        position: Point(2, 1),
+      })
+    );
+    let steps: Vec<_> = network.traverse().collect();
+    assert_eq!(steps.len(), 11);
+    assert!(steps[9].is_ok());
+  }
+
+  #[test]
+  fn ambiguous_junction() {
+    let network: Network = "  |\n  |\n -+-\n".parse().unwrap();
+    assert_eq!(
+      network.length(),
+      Err(MapError::AmbiguousJunction {
+        position: Point(2, 2),
+      })
+    );
   }
 }
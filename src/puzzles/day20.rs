@@ -1,23 +1,116 @@
-use std::str;
+//! Day 20: Particle Swarm
+//!
+//! Particles can be simulated step by step, but their motion
+//! is also simple enough to solve exactly. After `t` steps a
+//! particle is at `p + v t + a t (t + 1) / 2` on each axis,
+//! so doubling gives a quadratic in `t` with integer coefficients.
+
+use std::cmp::Ordering;
 use std::collections::HashMap;
+use std::fmt;
+use std::num::ParseIntError;
+use std::ops::{Add, AddAssign, Sub};
+use std::str::FromStr;
+
+#[derive(Debug, Fail, Clone, PartialEq, Eq)]
+pub enum ParticleError {
+  #[fail(display = "expected a {}=<x,y,z> vector", kind)] MissingVector { kind: char },
+
+  #[fail(display = "{} vector is missing its closing '>'", kind)] UnterminatedVector {
+    kind: char,
+  },
+
+  #[fail(display = "invalid number \"{}\" in {} vector", text, kind)]
+  InvalidNumber {
+    kind: char,
+    text: String,
+    #[cause] cause: ParseIntError,
+  },
+
+  #[fail(display = "{} vector has {} components, expected 3", kind, count)]
+  WrongComponents { kind: char, count: usize },
 
-use std::io;
-use std::io::Write;
+  #[fail(display = "unexpected \"{}\" after the particle", text)] TrailingText {
+    text: String,
+  },
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
-pub struct Point {
-  x: i32,
-  y: i32,
-  z: i32,
+  #[fail(display = "{} in {} vector is larger than 2^59 in magnitude", value, kind)]
+  OutOfRange { kind: char, value: i64 },
 }
 
-impl Point {
-  fn origin() -> Point {
-    Point { x: 0, y: 0, z: 0 }
+type Result<T> = ::std::result::Result<T, ParticleError>;
+
+/// A three dimensional vector.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Vec3<T> {
+  pub x: T,
+  pub y: T,
+  pub z: T,
+}
+
+impl<T> Vec3<T> {
+  pub fn new(x: T, y: T, z: T) -> Self {
+    Vec3 { x, y, z }
   }
+}
+
+impl<T: Add<Output = T>> Add for Vec3<T> {
+  type Output = Vec3<T>;
+
+  fn add(self, other: Vec3<T>) -> Vec3<T> {
+    Vec3::new(self.x + other.x, self.y + other.y, self.z + other.z)
+  }
+}
+
+impl<T: AddAssign> AddAssign for Vec3<T> {
+  fn add_assign(&mut self, other: Vec3<T>) {
+    self.x += other.x;
+    self.y += other.y;
+    self.z += other.z;
+  }
+}
+
+impl<T: Sub<Output = T>> Sub for Vec3<T> {
+  type Output = Vec3<T>;
 
-  fn distance(&self, other: &Point) -> u32 {
-    ((self.x - other.x).abs() + (self.y - other.y).abs() + (self.z - other.z).abs()) as u32
+  fn sub(self, other: Vec3<T>) -> Vec3<T> {
+    Vec3::new(self.x - other.x, self.y - other.y, self.z - other.z)
+  }
+}
+
+impl Vec3<i64> {
+  /// The Manhattan distance from the origin.
+  pub fn manhattan(&self) -> u64 {
+    self.x.unsigned_abs() + self.y.unsigned_abs() + self.z.unsigned_abs()
+  }
+}
+
+pub type Point = Vec3<i64>;
+
+/// The largest magnitude allowed for any component of a particle.
+/// Within this, the quadratics solved to find collisions, and
+/// their discriminants, all fit in an `i128`.
+pub const MAX_COMPONENT: i64 = 1 << 59;
+
+/// Check that every component of a vector is within `MAX_COMPONENT`.
+fn check_range(point: Point, kind: char) -> Result<Point> {
+  match [point.x, point.y, point.z]
+    .iter()
+    .find(|c| c.unsigned_abs() > MAX_COMPONENT as u64)
+  {
+    Some(&value) => Err(ParticleError::OutOfRange { kind, value }),
+    None => Ok(point),
+  }
+}
+
+/// Identifies a particle by its position in the input,
+/// which doesn't change as other particles are destroyed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ParticleId(pub usize);
+
+impl fmt::Display for ParticleId {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "{}", self.0)
   }
 }
 
@@ -28,20 +121,101 @@ pub struct Particle {
   a: Point,
 }
 
-type ParseResult<T> = Result<T, <i32 as str::FromStr>::Err>;
+impl FromStr for Particle {
+  type Err = ParticleError;
+
+  /// Parse a particle written as `p=<x,y,z>, v=<x,y,z>, a=<x,y,z>`.
+  fn from_str(text: &str) -> Result<Particle> {
+    let (p, rest) = parse_vector(text, 'p')?;
+    let (v, rest) = parse_vector(rest.trim_start().trim_start_matches(','), 'v')?;
+    let (a, rest) = parse_vector(rest.trim_start().trim_start_matches(','), 'a')?;
+    if !rest.trim().is_empty() {
+      return Err(ParticleError::TrailingText {
+        text: rest.trim().to_string(),
+      });
+    }
+    Particle::new(p, v, a)
+  }
+}
 
 impl Particle {
-  pub fn distance(&self) -> u32 {
-    return self.p.distance(&Point::origin());
+  /// A particle, as long as every component is within `MAX_COMPONENT`.
+  pub fn new(position: Point, velocity: Point, acceleration: Point) -> Result<Particle> {
+    Ok(Particle {
+      p: check_range(position, 'p')?,
+      v: check_range(velocity, 'v')?,
+      a: check_range(acceleration, 'a')?,
+    })
+  }
+
+  pub fn position(&self) -> Point {
+    self.p
+  }
+
+  pub fn velocity(&self) -> Point {
+    self.v
+  }
+
+  pub fn acceleration(&self) -> Point {
+    self.a
+  }
+
+  pub fn distance(&self) -> u64 {
+    self.p.manhattan()
   }
 
   fn advance(&mut self) {
-    self.v.x += self.a.x;
-    self.v.y += self.a.y;
-    self.v.z += self.a.z;
-    self.p.x += self.v.x;
-    self.p.y += self.v.y;
-    self.p.z += self.v.z;
+    self.v += self.a;
+    self.p += self.v;
+  }
+
+  /// The doubled position on each axis as a
+  /// quadratic in the number of steps, `[t^2, t, 1]`.
+  fn motion(&self) -> [[i128; 3]; 3] {
+    let axis = |p: i64, v: i64, a: i64| {
+      let (p, v, a) = (p as i128, v as i128, a as i128);
+      [a, 2 * v + a, 2 * p]
+    };
+    [
+      axis(self.p.x, self.v.x, self.a.x),
+      axis(self.p.y, self.v.y, self.a.y),
+      axis(self.p.z, self.v.z, self.a.z),
+    ]
+  }
+
+  /// The doubled distance from the origin once every axis has
+  /// stopped changing direction, as a quadratic `[t^2, t, 1]`.
+  /// Comparing these compares acceleration, then velocity,
+  /// then position, for the long-run distance.
+  fn long_run(&self) -> [i128; 3] {
+    let mut distance = [0; 3];
+    for axis in self.motion().iter() {
+      let sign = axis.iter().map(|c| c.signum()).find(|&s| s != 0).unwrap_or(0);
+      for (d, c) in distance.iter_mut().zip(axis.iter()) {
+        *d += sign * c;
+      }
+    }
+    distance
+  }
+
+  /// The first step after which two particles are in the same place.
+  pub fn collision_time(&self, other: &Particle) -> Option<u64> {
+    let (mine, theirs) = (self.motion(), other.motion());
+    let mut times: Option<Vec<u64>> = None;
+    for (m, t) in mine.iter().zip(theirs.iter()) {
+      let roots = match quadratic_roots(m[0] - t[0], m[1] - t[1], m[2] - t[2]) {
+        Roots::Always => continue,
+        Roots::At(roots) => roots,
+      };
+      times = Some(match times {
+        None => roots,
+        Some(times) => times.into_iter().filter(|t| roots.contains(t)).collect(),
+      });
+    }
+    match times {
+      Some(times) => times.into_iter().min(),
+      None => Some(1),
+    }
   }
 
   pub fn settled(&self) -> bool {
@@ -59,118 +233,252 @@ impl Particle {
       && yps * yas >= 0 && zps * zvs >= 0 && zvs * zas >= 0 && zps * zas >= 0
   }
 
-  pub fn parse(text: &str) -> ParseResult<Particle> {
-    let mut vectors = text
-      .split(">")
-      .map(|x| parse_vector(x.trim_left_matches(",").trim()));
+  pub fn parse(text: &str) -> Result<Particle> {
+    text.parse()
+  }
+}
+
+/// Parse all the particles in a list, one per line, with
+/// the line numbers of any which can't be parsed.
+pub fn parse_particles(text: &str) -> ::std::result::Result<Vec<Particle>, (usize, ParticleError)> {
+  text
+    .lines()
+    .enumerate()
+    .map(|(i, line)| line.parse().map_err(|e| (i + 1, e)))
+    .collect()
+}
+
+/// Particles destroyed by colliding with each other.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Collision {
+  /// The step after which the particles collided.
+  pub step: usize,
 
-    let (_p, pv) = vectors.next().unwrap()?;
-    let (_v, vv) = vectors.next().unwrap()?;
-    let (_a, av) = vectors.next().unwrap()?;
+  /// Where the particles collided.
+  pub position: Point,
 
-    Ok(Particle {
-      p: pv,
-      v: vv,
-      a: av,
-    })
-  }
+  /// The particles destroyed.
+  pub ids: Vec<ParticleId>,
 }
 
+/// Steps through a simulation, yielding the step number
+/// and the remaining particles, with their ids. If there is
+/// a check, steps where it doesn't hold are skipped.
 pub struct ParticleSimulationIterator<'a> {
   particles: Vec<Particle>,
+  ids: Vec<ParticleId>,
   step: usize,
-  check: Option<&'a Fn(&[Particle]) -> bool>,
+  check: Option<&'a dyn Fn(&[Particle]) -> bool>,
   collisions: bool,
+  observer: Option<&'a mut dyn FnMut(&Collision)>,
+}
+
+impl<'a> ParticleSimulationIterator<'a> {
+  /// Call `observer` with every collision, as it happens.
+  pub fn with_observer(mut self, observer: &'a mut dyn FnMut(&Collision)) -> Self {
+    self.observer = Some(observer);
+    self
+  }
+
+  /// Remove every particle which shares its position with
+  /// another, returning the collisions in order of lowest id.
+  fn prune_collisions(&mut self) -> Vec<Collision> {
+    let mut positions: HashMap<Point, Vec<ParticleId>> = HashMap::new();
+    for (particle, &id) in self.particles.iter().zip(self.ids.iter()) {
+      positions.entry(particle.p).or_default().push(id);
+    }
+
+    let mut collisions: Vec<Collision> = positions
+      .into_iter()
+      .filter(|(_, ids)| ids.len() > 1)
+      .map(|(position, ids)| Collision {
+        step: self.step,
+        position,
+        ids,
+      })
+      .collect();
+    if collisions.is_empty() {
+      return collisions;
+    }
+    collisions.sort_by_key(|c| c.ids[0]);
+
+    let (particles, ids) = self
+      .particles
+      .iter()
+      .zip(self.ids.iter())
+      .filter(|&(particle, _)| !collisions.iter().any(|c| c.position == particle.p))
+      .unzip();
+    self.particles = particles;
+    self.ids = ids;
+    collisions
+  }
 }
 
 impl<'a> Iterator for ParticleSimulationIterator<'a> {
-  type Item = (usize, Vec<Particle>);
+  type Item = (usize, Vec<(ParticleId, Particle)>);
 
   fn next(&mut self) -> Option<Self::Item> {
     loop {
       for particle in self.particles.iter_mut() {
         particle.advance();
       }
+      self.step += 1;
+
       if self.collisions {
-        let clen = self.particles.len();
-        self.particles = prune_collisions(&self.particles);
-        if clen != self.particles.len() {
-          print!("  {} particles remain", self.particles.len());
-          io::stdout().flush().unwrap();
-          print!("\r");
-          io::stdout().flush().unwrap();
+        for collision in self.prune_collisions() {
+          if let Some(ref mut observer) = self.observer {
+            observer(&collision);
+          }
         }
       }
 
-      self.step += 1;
-      if let Some(check) = self.check {
-        if check(&self.particles) {
-          return Some((self.step, self.particles.clone()));
-        }
-      } else {
-        return Some((self.step, self.particles.clone()));
+      if self.check.is_none_or(|check| check(&self.particles)) {
+        let particles = self.ids.iter().cloned().zip(self.particles.iter().cloned());
+        return Some((self.step, particles.collect()));
       }
     }
   }
 }
 
+/// Simulate particles step by step. Particle ids are their
+/// positions in `particles`.
 pub fn simulate<'a>(
   particles: Vec<Particle>,
-  check: Option<&'a Fn(&[Particle]) -> bool>,
+  check: Option<&'a dyn Fn(&[Particle]) -> bool>,
   collide: bool,
 ) -> ParticleSimulationIterator<'a> {
   ParticleSimulationIterator {
+    ids: (0..particles.len()).map(ParticleId).collect(),
     particles: particles,
     step: 0,
     check: check,
     collisions: collide,
+    observer: None,
+  }
+}
+
+/// Parse a vector of the given kind from the start of
+/// the text, returning it with the rest of the text.
+fn parse_vector(text: &str, kind: char) -> Result<(Point, &str)> {
+  let text = text.trim_start();
+  let prefix = format!("{}=<", kind);
+  if !text.starts_with(&prefix) {
+    return Err(ParticleError::MissingVector { kind });
   }
+  // The closing '>' must come before the next vector starts.
+  let body = &text[prefix.len()..];
+  let end = match body.find(&['>', '<', '='][..]) {
+    Some(end) if body[end..].starts_with('>') => end,
+    _ => return Err(ParticleError::UnterminatedVector { kind }),
+  };
+  let components = body[..end]
+    .split(',')
+    .map(|c| {
+      c.trim().parse::<i64>().map_err(|cause| ParticleError::InvalidNumber {
+        kind,
+        text: c.trim().to_string(),
+        cause,
+      })
+    })
+    .collect::<Result<Vec<i64>>>()?;
+  if components.len() != 3 {
+    return Err(ParticleError::WrongComponents {
+      kind,
+      count: components.len(),
+    });
+  }
+  let point = Vec3::new(components[0], components[1], components[2]);
+  Ok((point, &body[end + 1..]))
+}
+
+/// The steps at which a quadratic is zero.
+enum Roots {
+  Always,
+  At(Vec<u64>),
 }
 
-fn prune_collisions(particles: &[Particle]) -> Vec<Particle> {
-  let mut counter = HashMap::new();
+/// The positive integer roots of `a t^2 + b t + c`.
+fn quadratic_roots(a: i128, b: i128, c: i128) -> Roots {
+  let mut fractions = Vec::new();
+  if a == 0 {
+    if b == 0 {
+      return if c == 0 { Roots::Always } else { Roots::At(Vec::new()) };
+    }
+    fractions.push((-c, b));
+  } else {
+    let discriminant = b * b - 4 * a * c;
+    if discriminant < 0 {
+      return Roots::At(Vec::new());
+    }
+    let mut root = (discriminant as f64).sqrt() as i128;
+    while root * root > discriminant {
+      root -= 1;
+    }
+    while (root + 1) * (root + 1) <= discriminant {
+      root += 1;
+    }
+    if root * root != discriminant {
+      return Roots::At(Vec::new());
+    }
+    fractions.push((-b - root, 2 * a));
+    fractions.push((-b + root, 2 * a));
+  }
+  let mut roots: Vec<u64> = fractions
+    .into_iter()
+    .filter(|&(n, d)| n % d == 0 && n / d > 0)
+    .map(|(n, d)| (n / d) as u64)
+    .collect();
+  roots.dedup();
+  Roots::At(roots)
+}
 
-  for particle in particles.iter() {
-    let count = counter.entry(&particle.p).or_insert(0);
-    *count += 1;
+/// The particles left after every collision, found by
+/// solving for the time of each pairwise collision.
+/// Particle ids are their positions in `particles`.
+pub fn survivors(particles: &[Particle]) -> Vec<ParticleId> {
+  let mut collisions = Vec::new();
+  for (i, a) in particles.iter().enumerate() {
+    for (j, b) in particles.iter().enumerate().skip(i + 1) {
+      if let Some(t) = a.collision_time(b) {
+        collisions.push((t, i, j));
+      }
+    }
   }
+  collisions.sort();
 
-  particles
-    .iter()
-    .filter(|x| *counter.get(&x.p).unwrap() == 1)
-    .map(|x| *x)
+  // Particles destroyed at the same time can all collide with
+  // each other, so only mark them as destroyed after each step.
+  let mut destroyed: Vec<Option<u64>> = vec![None; particles.len()];
+  for &(t, i, j) in collisions.iter() {
+    let alive = |k: usize| destroyed[k].is_none_or(|d| d == t);
+    if alive(i) && alive(j) {
+      destroyed[i] = Some(t);
+      destroyed[j] = Some(t);
+    }
+  }
+  (0..particles.len())
+    .filter(|&i| destroyed[i].is_none())
+    .map(ParticleId)
     .collect()
 }
 
-fn parse_vector(text: &str) -> ParseResult<(char, Point)> {
-  let kind = text
-    .chars()
-    .nth(0)
-    .expect("Vector stirng has no characters!");
-  let vec: Vec<i32> = text.trim()[3..]
-    .split(",")
-    .map(|x| x.trim().parse::<i32>())
-    .collect::<ParseResult<Vec<i32>>>()?;
-  let point = Point {
-    x: vec[0],
-    y: vec[1],
-    z: vec[2],
-  };
-  Ok((kind, point))
+/// Compare particles by their distance from the origin in the long run.
+fn long_run_order(a: &Particle, b: &Particle) -> Ordering {
+  a.long_run().cmp(&b.long_run())
 }
 
-pub fn find_closest(particles: Vec<Particle>, collide: bool) -> usize {
-  let (_n, pf) = simulate(
-    particles,
-    Some(&|p: &[Particle]| -> bool { p.iter().all(|pi| pi.settled()) }),
-    collide,
-  ).nth(0)
-    .unwrap();
-  let (id, _pm) = pf.iter()
-    .enumerate()
-    .min_by_key(|&(_i, pi)| pi.distance())
-    .unwrap();
-  id
+/// The particle which stays closest to the origin in the long run,
+/// optionally after collisions. Ties go to the lowest id.
+pub fn find_closest(particles: Vec<Particle>, collide: bool) -> ParticleId {
+  let candidates = if collide {
+    survivors(&particles)
+  } else {
+    (0..particles.len()).map(ParticleId).collect()
+  };
+  candidates
+    .into_iter()
+    .min_by(|&i, &j| long_run_order(&particles[i.0], &particles[j.0]).then(i.cmp(&j)))
+    .expect("No particles left!")
 }
 
 #[cfg(test)]
@@ -181,13 +489,97 @@ mod test {
   fn test_parsing() {
     let p = Particle::parse(&"p=< 3,0,0>, v=< 2,0,0>, a=<-1,0,0>").unwrap();
     let ep = Particle {
-      p: Point { x: 3, y: 0, z: 0 },
-      v: Point { x: 2, y: 0, z: 0 },
-      a: Point { x: -1, y: 0, z: 0 },
+      p: Vec3::new(3, 0, 0),
+      v: Vec3::new(2, 0, 0),
+      a: Vec3::new(-1, 0, 0),
     };
     assert_eq!(p, ep);
   }
 
+  #[test]
+  fn test_parse_errors() {
+    assert_eq!(
+      Particle::parse("p=<1,2,3>, a=<0,0,0>"),
+      Err(ParticleError::MissingVector { kind: 'v' })
+    );
+    assert_eq!(
+      Particle::parse("p=<1,2,3>, v=<0,0,0>, a=<0,0"),
+      Err(ParticleError::UnterminatedVector { kind: 'a' })
+    );
+    assert_eq!(
+      Particle::parse("p=<1,2,3, v=<0,0,0>, a=<0,0,0>"),
+      Err(ParticleError::UnterminatedVector { kind: 'p' })
+    );
+    assert_eq!(
+      Particle::parse("p=<1,2>, v=<0,0,0>, a=<0,0,0>"),
+      Err(ParticleError::WrongComponents { kind: 'p', count: 2 })
+    );
+    assert_eq!(
+      Particle::parse("p=<1,2,3>, v=<0,0,0>, a=<0,0,0> b=<1,1,1>"),
+      Err(ParticleError::TrailingText {
+        text: "b=<1,1,1>".to_string(),
+      })
+    );
+    match Particle::parse("p=<1,x,3>, v=<0,0,0>, a=<0,0,0>") {
+      Err(ParticleError::InvalidNumber { kind: 'p', ref text, .. }) => assert_eq!(text, "x"),
+      other => panic!("unexpected {:?}", other),
+    }
+    assert_eq!(
+      parse_particles("p=<1,2,3>, v=<0,0,0>, a=<0,0,0>\np=<1,2,3>").map_err(|e| e.0),
+      Err(2)
+    );
+  }
+
+  #[test]
+  fn extreme_components() {
+    let max = MAX_COMPONENT;
+    assert_eq!(
+      Particle::parse(&format!("p=<0,0,0>, v=<0,{},0>, a=<0,0,0>", -max - 1)),
+      Err(ParticleError::OutOfRange {
+        kind: 'v',
+        value: -max - 1,
+      })
+    );
+    assert_eq!(
+      Particle::new(Vec3::default(), Vec3::default(), Vec3::new(0, 0, i64::max_value())),
+      Err(ParticleError::OutOfRange {
+        kind: 'a',
+        value: i64::max_value(),
+      })
+    );
+
+    let particles = vec![
+      Particle::new(Vec3::new(max, max, -max), Vec3::new(-max, -max, max), Vec3::default()),
+      Particle::new(Vec3::new(-max, -max, max), Vec3::new(max, max, -max), Vec3::default()),
+      Particle::new(
+        Vec3::new(max, -max, max),
+        Vec3::new(max, max, -max),
+        Vec3::new(max, -max, max),
+      ),
+      Particle::new(
+        Vec3::new(-max, max, -max),
+        Vec3::new(-max, -max, max),
+        Vec3::new(-max, max, -max),
+      ),
+      Particle::new(Vec3::new(1, 2, 3), Vec3::default(), Vec3::new(0, 0, 1)),
+    ].into_iter()
+      .collect::<Result<Vec<_>>>()
+      .unwrap();
+    assert_eq!(particles[0].collision_time(&particles[1]), Some(1));
+    assert_eq!(particles[2].collision_time(&particles[3]), None);
+    assert_eq!(find_closest(particles.clone(), false), ParticleId(0));
+    assert_eq!(find_closest(particles, true), ParticleId(4));
+  }
+
+  #[test]
+  fn test_large_values() {
+    let mut p = Particle::parse("p=<0,0,0>, v=<0,0,0>, a=<1000000,0,0>").unwrap();
+    for _ in 0..100_000 {
+      p.advance();
+    }
+    assert_eq!(p.distance(), 1_000_000 * 100_000 * 100_001 / 2);
+  }
+
   #[test]
   fn test_settled() {
     let p1 = Particle::parse(&"p=< 3,0,0>, v=< 2,0,0>, a=<-1,0,0>").unwrap();
@@ -207,12 +599,91 @@ mod test {
       false,
     ).nth(0)
       .unwrap();
-    let (id, _pm) = pf.iter()
-      .enumerate()
-      .min_by_key(|&(_i, pi)| pi.distance())
+    let &(id, _pm) = pf.iter()
+      .min_by_key(|&&(_i, pi)| pi.distance())
       .unwrap();
-    assert_eq!(id, 0);
+    assert_eq!(id, ParticleId(0));
     assert_eq!(n, 5);
-    assert_eq!(find_closest(ps.clone(), false), 0)
+    assert_eq!(find_closest(ps.clone(), false), ParticleId(0))
+  }
+
+  fn particles(text: &str) -> Vec<Particle> {
+    text.lines().map(|l| Particle::parse(l).unwrap()).collect()
+  }
+
+  #[test]
+  fn test_collision_time() {
+    let ps = particles(
+      "p=<-6,0,0>, v=< 3,0,0>, a=< 0,0,0>
+p=<-4,0,0>, v=< 2,0,0>, a=< 0,0,0>
+p=<-2,0,0>, v=< 1,0,0>, a=< 0,0,0>
+p=< 3,0,0>, v=<-1,0,0>, a=< 0,0,0>",
+    );
+    assert_eq!(ps[0].collision_time(&ps[1]), Some(2));
+    assert_eq!(ps[1].collision_time(&ps[2]), Some(2));
+    assert_eq!(ps[0].collision_time(&ps[3]), None);
+    assert_eq!(survivors(&ps), vec![ParticleId(3)]);
+  }
+
+  #[test]
+  fn test_accelerating_collision() {
+    let ps = particles(
+      "p=<0,0,0>, v=<0,0,0>, a=<2,0,0>
+p=<6,0,0>, v=<-1,0,0>, a=<0,0,0>
+p=<1,5,0>, v=<0,-1,0>, a=<0,0,0>",
+    );
+    assert_eq!(ps[0].collision_time(&ps[1]), None);
+    assert_eq!(ps[0].collision_time(&ps[2]), None);
+    let ps = particles(
+      "p=<0,0,0>, v=<0,0,0>, a=<2,0,0>
+p=<8,0,0>, v=<-1,0,0>, a=<0,0,0>",
+    );
+    // Along x, 2, 6, ... and 7, 6, ...
+    assert_eq!(ps[0].collision_time(&ps[1]), Some(2));
+  }
+
+  #[test]
+  fn test_collision_events() {
+    let ps = particles(
+      "p=< 3,0,0>, v=< 0,0,0>, a=< 0,0,0>
+p=<-6,0,0>, v=< 3,0,0>, a=< 0,0,0>
+p=<-4,0,0>, v=< 2,0,0>, a=< 0,0,0>
+p=<-2,0,0>, v=< 1,0,0>, a=< 0,0,0>
+p=< 5,0,0>, v=<-1,0,0>, a=< 0,0,0>",
+    );
+    let mut collisions = Vec::new();
+    let remaining = {
+      let mut observer = |c: &Collision| collisions.push(c.clone());
+      let mut simulation = simulate(ps, None, true).with_observer(&mut observer);
+      simulation.nth(3).unwrap().1
+    };
+    assert_eq!(
+      collisions,
+      vec![
+        Collision {
+          step: 2,
+          position: Vec3::new(3, 0, 0),
+          ids: vec![ParticleId(0), ParticleId(4)],
+        },
+        Collision {
+          step: 2,
+          position: Vec3::new(0, 0, 0),
+          ids: vec![ParticleId(1), ParticleId(2), ParticleId(3)],
+        },
+      ]
+    );
+    assert!(remaining.is_empty());
+  }
+
+  #[test]
+  fn test_long_run() {
+    let ps = particles(
+      "p=<100,0,0>, v=<0,0,0>, a=<1,0,0>
+p=<-5,0,0>, v=<-2,0,0>, a=<0,0,-1>
+p=<0,0,0>, v=<-3,0,0>, a=<0,0,1>",
+    );
+    // All accelerate equally, and particle 0 is slowest,
+    // even though it starts furthest away.
+    assert_eq!(find_closest(ps, false), ParticleId(0));
   }
 }
@@ -4,9 +4,12 @@
 
 use std::str::FromStr;
 use std::collections::{HashMap, HashSet};
+use std::collections::hash_map::Entry;
 use std::fmt;
 use std::io;
 
+use super::super::netpbm;
+
 /// Module level error type used to handle the various
 /// error cases where rule construction might fail.
 #[derive(Debug, Fail)]
@@ -34,6 +37,36 @@ pub enum PatchError {
     patch: Patch,
   },
 
+  #[fail(display = "conflicting rules for \"{}\"", patch)] ConflictingRule {
+    patch: Patch,
+  },
+
+  #[fail(display = "rule maps a {0}x{0} patch to a {1}x{1} patch, expected 2x2 to 3x3 or \
+                     3x3 to 4x4",
+         input, output)]
+  InvalidRuleSize {
+    input: usize,
+    output: usize,
+  },
+
+  #[fail(display = "{} patches can't be assembled into a square", count)]
+  NonSquareCount {
+    count: usize,
+  },
+
+  #[fail(display = "patch {0} is {1}x{1}, expected {2}x{2} like the first patch", index, size,
+         expected)]
+  MismatchedPatch {
+    index: usize,
+    size: usize,
+    expected: usize,
+  },
+
+  #[fail(display = "too many cells are on after {} iterations to count", iterations)]
+  CountOverflow {
+    iterations: usize,
+  },
+
   #[fail(display = "{}", _0)] Io(#[cause] io::Error),
 }
 
@@ -101,6 +134,22 @@ impl fmt::Display for Patch {
   }
 }
 
+impl netpbm::Grid for Patch {
+  type Cell = bool;
+
+  fn width(&self) -> usize {
+    self.size()
+  }
+
+  fn height(&self) -> usize {
+    self.size()
+  }
+
+  fn cell(&self, x: usize, y: usize) -> bool {
+    self.get((y, x))
+  }
+}
+
 impl Default for Patch {
   /// Get the default starting patch
   /// for the day 21 puzzle:
@@ -142,7 +191,6 @@ impl Patch {
     patch
   }
 
-  #[allow(dead_code)]
   fn combine(patches: &[&Patch]) -> Self {
     let subpatch_side = patches[0].size();
     let num_subpatch_side = (patches.len() as f64).sqrt() as usize;
@@ -339,6 +387,132 @@ pub fn after_n<'a>(patterns: &'a PatternSet, n: usize) -> Result<Vec<&'a Patch>>
   Ok(patterns.iter()?.take(n).last().unwrap()?)
 }
 
+/// Parse a rule of the form `../.# => ##./#../...`.
+fn parse_rule(s: &str) -> Result<(Patch, Patch)> {
+  let mut parts = s.split("=>");
+  let mut patch = || {
+    parts
+      .next()
+      .ok_or_else(|| PatchError::RuleError {
+        rule: s.to_string(),
+      })?
+      .parse::<Patch>()
+  };
+  let left = patch()?;
+  let right = patch()?;
+  Ok((left, right))
+}
+
+/// A problem found in a rule book, and the
+/// line it was found on if it has one.
+#[derive(Debug)]
+pub struct Diagnostic {
+  pub line: Option<usize>,
+  pub error: PatchError,
+}
+
+impl fmt::Display for Diagnostic {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self.line {
+      Some(line) => write!(f, "line {}: {}", line, self.error),
+      None => write!(f, "{}", self.error),
+    }
+  }
+}
+
+/// The result of checking a rule book.
+#[derive(Debug, Default)]
+pub struct Validation {
+  /// Rules which can't be parsed, rules with the wrong sizes,
+  /// rules which conflict with an earlier rule, and patches
+  /// with no rule at all.
+  pub errors: Vec<Diagnostic>,
+
+  /// Rules which repeat an earlier rule, with the same output.
+  pub warnings: Vec<Diagnostic>,
+}
+
+impl Validation {
+  pub fn is_ok(&self) -> bool {
+    self.errors.is_empty()
+  }
+}
+
+/// Check a rule book, one rule per line. Line numbers start at 1,
+/// and blank lines are ignored. Every 2x2 and 3x3 patch must be
+/// covered by exactly one rule, up to rotations and flips.
+pub fn validate<'a, I>(lines: I) -> Validation
+where
+  I: IntoIterator<Item = &'a str>,
+{
+  let mut validation = Validation::default();
+  let mut seen: HashMap<(usize, u16), Patch> = HashMap::new();
+  for (i, line) in lines.into_iter().enumerate() {
+    let line_number = Some(i + 1);
+    if line.trim().is_empty() {
+      continue;
+    }
+    let (input, output) = match parse_rule(line) {
+      Ok(rule) => rule,
+      Err(error) => {
+        validation.errors.push(Diagnostic {
+          line: line_number,
+          error,
+        });
+        continue;
+      }
+    };
+
+    let size = input.size();
+    if (size != 2 && size != 3) || output.size() != size + 1 {
+      validation.errors.push(Diagnostic {
+        line: line_number,
+        error: PatchError::InvalidRuleSize {
+          input: size,
+          output: output.size(),
+        },
+      });
+      continue;
+    }
+
+    let key = canonical(size, pack(size, |row, col| input.get((row, col))));
+    match seen.entry((size, key)) {
+      Entry::Vacant(entry) => {
+        entry.insert(output);
+      }
+      Entry::Occupied(entry) => {
+        let diagnostic = Diagnostic {
+          line: line_number,
+          error: if *entry.get() == output {
+            PatchError::DuplicateRule { patch: input }
+          } else {
+            PatchError::ConflictingRule { patch: input }
+          },
+        };
+        if *entry.get() == output {
+          validation.warnings.push(diagnostic);
+        } else {
+          validation.errors.push(diagnostic);
+        }
+      }
+    }
+  }
+
+  for &size in [2, 3].iter() {
+    for bits in 0..1 << (size * size) {
+      if canonical(size, bits) == bits && !seen.contains_key(&(size, bits)) {
+        validation.errors.push(Diagnostic {
+          line: None,
+          error: PatchError::MissingRule {
+            patch: unpack(size, bits),
+          },
+        });
+      }
+    }
+  }
+  validation
+}
+
 /// A collection of rules for transforming one patch
 /// into another patch. Patch rules are invariant over the input
 /// for rotation and flips. When a new
@@ -354,14 +528,21 @@ impl PatternSet {
     }
   }
 
+  /// Insert a rule for every variant of `left`. Repeating a
+  /// rule is harmless, but giving a patch a second, different
+  /// output is an error, and leaves the set unchanged.
   fn insert_patch(&mut self, left: &Patch, right: &Patch) -> Result<()> {
-    for variant in left.variants() {
-      match self.patterns.insert(variant, right.clone()) {
-        Some(patch) => {
-          return Err(PatchError::DuplicateRule { patch });
-        }
-        None => {}
-      };
+    let variants = left.variants();
+    if variants
+      .iter()
+      .any(|v| self.patterns.get(v).is_some_and(|existing| existing != right))
+    {
+      return Err(PatchError::ConflictingRule {
+        patch: left.clone(),
+      });
+    }
+    for variant in variants {
+      self.patterns.insert(variant, right.clone());
     }
     Ok(())
   }
@@ -371,21 +552,7 @@ impl PatternSet {
   /// rotating and flipping the left side (rule trigger) as appropriate.
   /// Input rules should have the form `../.# => ##./#../...`.
   pub fn insert(&mut self, s: &str) -> Result<()> {
-    let mut parts = s.split("=>");
-
-    let left: Patch = parts
-      .next()
-      .ok_or_else(|| PatchError::RuleError {
-        rule: s.to_string(),
-      })?
-      .parse()?;
-    let right: Patch = parts
-      .next()
-      .ok_or_else(|| PatchError::RuleError {
-        rule: s.to_string(),
-      })?
-      .parse()?;
-
+    let (left, right) = parse_rule(s)?;
     self.insert_patch(&left, &right)
   }
 
@@ -415,13 +582,18 @@ impl PatternSet {
 
   /// Iterate over artwork, from the starting point.
   pub fn iter<'a>(&'a self) -> Result<PatternIterator<'a>> {
-    let p = Patch::default();
+    self.iter_from(Patch::default())
+  }
+
+  /// Iterate over artwork, from any starting patch
+  /// which can be split into 2x2 or 3x3 patches.
+  pub fn iter_from<'a>(&'a self, start: Patch) -> Result<PatternIterator<'a>> {
+    if start.size() % 2 != 0 && start.size() % 3 != 0 {
+      return Err(PatchError::UnsplittablePatch { size: start.size() });
+    }
     Ok(PatternIterator {
-      patterns: Some(vec![
-        self
-          .getkey(&p)
-          .ok_or_else(|| PatchError::MissingRule { patch: p.clone() })?,
-      ]),
+      start: Some(start),
+      patterns: Some(Vec::new()),
       rules: self,
     })
   }
@@ -429,6 +601,7 @@ impl PatternSet {
 
 /// An iterator over artworks that the program produces.
 pub struct PatternIterator<'a> {
+  start: Option<Patch>,
   patterns: Option<Vec<&'a Patch>>,
   rules: &'a PatternSet,
 }
@@ -437,14 +610,16 @@ impl<'a> Iterator for PatternIterator<'a> {
   type Item = Result<Vec<&'a Patch>>;
 
   fn next(&mut self) -> Option<Self::Item> {
-    let patterns = match self.patterns {
-      Some(ref patterns) => Patch::combine(&patterns)
-        .split()
-        .and_then(|ps| self.rules.transform(&ps.iter().collect::<Vec<_>>())),
-      None => {
+    let combined = match (self.start.take(), &self.patterns) {
+      (Some(start), _) => start,
+      (None, Some(patterns)) => Patch::combine(patterns),
+      (None, None) => {
         return None;
       }
     };
+    let patterns = combined
+      .split()
+      .and_then(|ps| self.rules.transform(&ps.iter().collect::<Vec<_>>()));
 
     match patterns {
       Ok(ps) => {
@@ -459,6 +634,393 @@ impl<'a> Iterator for PatternIterator<'a> {
   }
 }
 
+/// Pack a square block of cells into an integer, one bit per
+/// cell in row-major order. Blocks of up to 4x4 fit in a `u16`.
+fn pack<F>(size: usize, cell: F) -> u16
+where
+  F: Fn(usize, usize) -> bool,
+{
+  let mut bits = 0;
+  for row in 0..size {
+    for col in 0..size {
+      if cell(row, col) {
+        bits |= 1 << (row * size + col);
+      }
+    }
+  }
+  bits
+}
+
+fn unpack(size: usize, bits: u16) -> Patch {
+  let mut patch = Patch::new(size);
+  for row in 0..size {
+    for col in 0..size {
+      patch.set((row, col), bits & (1 << (row * size + col)) != 0);
+    }
+  }
+  patch
+}
+
+/// The smallest packing of any rotation or flip of a block.
+fn canonical(size: usize, bits: u16) -> u16 {
+  let get = |row: usize, col: usize| bits & (1 << (row * size + col)) != 0;
+  let last = size - 1;
+  (0..8)
+    .map(|symmetry| {
+      pack(size, |row, col| {
+        let (row, col) = if symmetry & 4 == 0 {
+          (row, col)
+        } else {
+          (row, last - col)
+        };
+        match symmetry & 3 {
+          0 => get(row, col),
+          1 => get(col, last - row),
+          2 => get(last - row, last - col),
+          _ => get(last - col, row),
+        }
+      })
+    })
+    .min()
+    .unwrap()
+}
+
+/// A whole piece of artwork, assembled into one square image.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Artwork {
+  size: usize,
+  cells: Vec<bool>,
+}
+
+impl Artwork {
+  /// A blank piece of artwork, with every pixel off.
+  pub fn new(size: usize) -> Self {
+    Artwork {
+      size,
+      cells: vec![false; size * size],
+    }
+  }
+
+  pub fn from_patch(patch: &Patch) -> Self {
+    let size = patch.size();
+    Artwork {
+      size,
+      cells: (0..size * size)
+        .map(|i| patch.get((i / size, i % size)))
+        .collect(),
+    }
+  }
+
+  /// Assemble the patches yielded by a `PatternIterator`,
+  /// which are in row order, into one image. There must be a
+  /// square number of patches, all the same size.
+  pub fn from_patches(patches: &[&Patch]) -> Result<Self> {
+    let count = patches.len();
+    let side = (0..).find(|&side| side * side >= count).unwrap();
+    if count == 0 || side * side != count {
+      return Err(PatchError::NonSquareCount { count });
+    }
+    let expected = patches[0].size();
+    if let Some((index, patch)) = patches
+      .iter()
+      .enumerate()
+      .find(|&(_, patch)| patch.size() != expected)
+    {
+      return Err(PatchError::MismatchedPatch {
+        index,
+        size: patch.size(),
+        expected,
+      });
+    }
+    Ok(Artwork::from_patch(&Patch::combine(patches)))
+  }
+
+  /// The number of pixels along each side.
+  pub fn size(&self) -> usize {
+    self.size
+  }
+
+  /// The pixel at `(row, col)`, if it is inside the image.
+  pub fn get(&self, row: usize, col: usize) -> Option<bool> {
+    if row < self.size && col < self.size {
+      Some(self.cells[row * self.size + col])
+    } else {
+      None
+    }
+  }
+
+  /// Turn the pixel at `(row, col)` on or off.
+  ///
+  /// # Panics
+  ///
+  /// If `(row, col)` is outside the image.
+  pub fn set(&mut self, row: usize, col: usize, value: bool) {
+    assert!(row < self.size && col < self.size, "pixel ({}, {}) outside image", row, col);
+    self.cells[row * self.size + col] = value;
+  }
+
+  /// The number of pixels which are on in the region of `height`
+  /// rows and `width` columns whose top left is at `(row, col)`.
+  /// The region is clipped to the image.
+  pub fn on_in(&self, row: usize, col: usize, height: usize, width: usize) -> usize {
+    let rows = row.min(self.size)..(row + height).min(self.size);
+    let cols = col.min(self.size)..(col + width).min(self.size);
+    rows
+      .map(|r| {
+        self.cells[r * self.size + cols.start..r * self.size + cols.end]
+          .iter()
+          .filter(|&&c| c)
+          .count()
+      })
+      .sum()
+  }
+
+  /// The number of pixels which are on in each block of `size`
+  /// pixels, by row and then column. Blocks at the right and
+  /// bottom edges are smaller if `size` doesn't divide the image.
+  /// Blocks of size 0 hold nothing, so there are none.
+  pub fn on_by_block(&self, size: usize) -> Vec<Vec<usize>> {
+    if size == 0 {
+      return Vec::new();
+    }
+    let blocks = (self.size + size - 1) / size;
+    (0..blocks)
+      .map(|row| {
+        (0..blocks)
+          .map(|col| self.on_in(row * size, col * size, size, size))
+          .collect()
+      })
+      .collect()
+  }
+
+  /// The block of `size` cells whose top left is at `(row, col)`.
+  fn block(&self, row: usize, col: usize, size: usize) -> u16 {
+    pack(size, |r, c| self.cells[(row + r) * self.size + col + c])
+  }
+
+  fn set_block(&mut self, row: usize, col: usize, size: usize, bits: u16) {
+    for r in 0..size {
+      for c in 0..size {
+        self.cells[(row + r) * self.size + col + c] = bits & (1 << (r * size + c)) != 0;
+      }
+    }
+  }
+
+  /// The number of pixels which are on.
+  pub fn on(&self) -> usize {
+    self.cells.iter().filter(|&&c| c).count()
+  }
+
+  /// Every 3x3 block evolves on its own once the image is an odd
+  /// multiple of 3 across: it is split into 3x3 blocks, and each of
+  /// those becomes 4x4, then 6x6 in 2x2 blocks, then 9x9 again.
+  fn independent_blocks(&self) -> bool {
+    self.size % 2 != 0 && self.size % 3 == 0
+  }
+}
+
+impl fmt::Display for Artwork {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    if self.size == 0 {
+      return Ok(());
+    }
+    for row in self.cells.chunks(self.size) {
+      for &col in row {
+        write!(f, "{}", if col { '#' } else { '.' })?;
+      }
+      writeln!(f)?;
+    }
+    Ok(())
+  }
+}
+
+impl netpbm::Grid for Artwork {
+  type Cell = bool;
+
+  fn width(&self) -> usize {
+    self.size
+  }
+
+  fn height(&self) -> usize {
+    self.size
+  }
+
+  fn cell(&self, x: usize, y: usize) -> bool {
+    self.cells[y * self.size + x]
+  }
+}
+
+/// A rule book packed for fast lookup. Each block is looked up by
+/// the canonical packing of its input, the smallest over all
+/// rotations and flips, so each rule is only stored once.
+pub struct Rulebook {
+  /// The canonical packing of every 2x2 and 3x3 block.
+  canonical: [Vec<u16>; 2],
+
+  /// The output of each rule, by canonical input.
+  rules: [Vec<Option<u16>>; 2],
+}
+
+impl Rulebook {
+  /// Pack the rules from a pattern set.
+  pub fn new(patterns: &PatternSet) -> Result<Self> {
+    let table = [
+      (0..1 << 4).map(|bits| canonical(2, bits)).collect(),
+      (0..1 << 9).map(|bits| canonical(3, bits)).collect(),
+    ];
+    let mut rules = [vec![None; 1 << 4], vec![None; 1 << 9]];
+    for (input, output) in patterns.patterns.iter() {
+      let size = input.size();
+      if (size != 2 && size != 3) || output.size() != size + 1 {
+        return Err(PatchError::RuleError {
+          rule: format!("{:?} => {:?}", input, output),
+        });
+      }
+      let bits = pack(size, |row, col| input.get((row, col)));
+      let key = canonical(size, bits) as usize;
+      let packed = pack(size + 1, |row, col| output.get((row, col)));
+      match rules[size - 2][key] {
+        Some(existing) if existing != packed => {
+          return Err(PatchError::ConflictingRule {
+            patch: input.clone(),
+          });
+        }
+        _ => rules[size - 2][key] = Some(packed),
+      }
+    }
+    Ok(Rulebook {
+      canonical: table,
+      rules,
+    })
+  }
+
+  fn lookup(&self, size: usize, bits: u16) -> Result<u16> {
+    let key = self.canonical[size - 2][bits as usize] as usize;
+    self.rules[size - 2][key].ok_or_else(|| PatchError::MissingRule {
+      patch: unpack(size, bits),
+    })
+  }
+
+  /// The next generation of a piece of artwork.
+  pub fn step(&self, image: &Artwork) -> Result<Artwork> {
+    let size = match image.size {
+      x if x % 2 == 0 => 2,
+      x if x % 3 == 0 => 3,
+      x => return Err(PatchError::UnsplittablePatch { size: x }),
+    };
+    let blocks = image.size / size;
+    let mut next = Artwork::new(blocks * (size + 1));
+    for row in 0..blocks {
+      for col in 0..blocks {
+        let output = self.lookup(size, image.block(row * size, col * size, size))?;
+        next.set_block(row * (size + 1), col * (size + 1), size + 1, output);
+      }
+    }
+    Ok(next)
+  }
+
+  /// The canonical 3x3 blocks, with multiplicities,
+  /// which a 3x3 block becomes after three iterations.
+  fn expand(&self, block: u16) -> Result<Vec<(u16, u128)>> {
+    let mut image = Artwork::new(3);
+    image.set_block(0, 0, 3, block);
+    for _ in 0..3 {
+      image = self.step(&image)?;
+    }
+    Ok(self.count_blocks(&image).into_iter().collect())
+  }
+
+  fn count_blocks(&self, image: &Artwork) -> HashMap<u16, u128> {
+    let mut counts = HashMap::new();
+    for row in 0..image.size / 3 {
+      for col in 0..image.size / 3 {
+        let key = self.canonical[1][image.block(row * 3, col * 3, 3) as usize];
+        *counts.entry(key).or_insert(0) += 1;
+      }
+    }
+    counts
+  }
+
+  /// The number of cells which are on after `n` iterations from
+  /// `start`. Once the image splits into independent 3x3 blocks,
+  /// only the number of each kind of block is tracked. If the
+  /// count doesn't fit in a `u128`, which can happen after about
+  /// 118 iterations, this is a `CountOverflow` error.
+  pub fn count_on(&self, start: &Patch, n: usize) -> Result<u128> {
+    let overflow = || PatchError::CountOverflow { iterations: n };
+    let mut image = Artwork::from_patch(start);
+    let mut done = 0;
+    while done < n && !image.independent_blocks() {
+      image = self.step(&image)?;
+      done += 1;
+    }
+    if done == n {
+      return Ok(image.on() as u128);
+    }
+
+    let mut counts = self.count_blocks(&image);
+    let mut expansions: HashMap<u16, Vec<(u16, u128)>> = HashMap::new();
+    for _ in 0..(n - done) / 3 {
+      let mut next = HashMap::new();
+      for (&block, &count) in counts.iter() {
+        let children = match expansions.entry(block) {
+          Entry::Occupied(entry) => entry.into_mut(),
+          Entry::Vacant(entry) => entry.insert(self.expand(block)?),
+        };
+        for &(child, multiplicity) in children.iter() {
+          let total = next.entry(child).or_insert(0u128);
+          *total = count
+            .checked_mul(multiplicity)
+            .and_then(|c| total.checked_add(c))
+            .ok_or_else(overflow)?;
+        }
+      }
+      counts = next;
+    }
+
+    let mut on = 0u128;
+    for (&block, &count) in counts.iter() {
+      let mut image = Artwork::new(3);
+      image.set_block(0, 0, 3, block);
+      for _ in 0..(n - done) % 3 {
+        image = self.step(&image)?;
+      }
+      on = count
+        .checked_mul(image.on() as u128)
+        .and_then(|c| on.checked_add(c))
+        .ok_or_else(overflow)?;
+    }
+    Ok(on)
+  }
+
+  /// Iterate over every generation of artwork, starting with `start`.
+  pub fn generations<'a>(&'a self, start: &Patch) -> Generations<'a> {
+    Generations {
+      rulebook: self,
+      next: Some(Ok(Artwork::from_patch(start))),
+    }
+  }
+}
+
+/// An iterator over the generations of a piece of artwork.
+/// It stops after the first error.
+pub struct Generations<'a> {
+  rulebook: &'a Rulebook,
+  next: Option<Result<Artwork>>,
+}
+
+impl<'a> Iterator for Generations<'a> {
+  type Item = Result<Artwork>;
+
+  fn next(&mut self) -> Option<Self::Item> {
+    let current = self.next.take()?;
+    if let Ok(ref image) = current {
+      self.next = Some(self.rulebook.step(image));
+    }
+    Some(current)
+  }
+}
+
 #[cfg(test)]
 mod test {
 
@@ -551,6 +1113,256 @@ mod test {
     }
   }
 
+  #[test]
+  fn duplicate_rules() {
+    let mut rules = PatternSet::new();
+    rules.insert("../.# => ##./#../...").unwrap();
+    rules.insert(".#/.. => ##./#../...").unwrap();
+    match rules.insert("#./.. => ##./##./...") {
+      Err(PatchError::ConflictingRule { .. }) => {}
+      other => panic!("unexpected {:?}", other),
+    }
+    assert_eq!(
+      rules.get(&"../#.".parse().unwrap()),
+      Some(&"##./#../...".parse().unwrap())
+    );
+  }
+
+  #[test]
+  fn custom_start() {
+    let mut rules = PatternSet::new();
+    rules.insert("../.# => ##./#../...").unwrap();
+    rules.insert("../.. => .../.#./...").unwrap();
+    let start: Patch = "#..#/..../..../#..#".parse().unwrap();
+    let patches = rules.iter_from(start).unwrap().next().unwrap().unwrap();
+    assert_eq!(on(&patches), 12);
+
+    let rulebook = Rulebook::new(&rules).unwrap();
+    assert_eq!(
+      rulebook.count_on(&"#..#/..../..../#..#".parse().unwrap(), 1).unwrap(),
+      12
+    );
+    match rules.iter_from(Patch::new(5)) {
+      Err(PatchError::UnsplittablePatch { size: 5 }) => {}
+      other => panic!("unexpected {:?}", other.map(|_| ())),
+    }
+  }
+
+  #[test]
+  fn validate_rules() {
+    let validation = validate(vec![
+      "../.. => .../.../...",
+      "../.# => ##./#../...",
+      "",
+      ".#/.. => ##./#../...",
+      "#./.. => ##./##./...",
+      "##/.. => ..../..../..../....",
+      "##/## => ###",
+      ".#/#. => ##./#../...",
+    ]);
+
+    let lines = |ds: &[Diagnostic]| ds.iter().map(|d| d.line).collect::<Vec<_>>();
+    assert_eq!(lines(&validation.warnings), vec![Some(4)]);
+    match validation.warnings[0].error {
+      PatchError::DuplicateRule { .. } => {}
+      ref other => panic!("unexpected {:?}", other),
+    }
+
+    // Conflict, wrong size, unparseable, then uncovered
+    // 2x2 patches (##/.. and ##/#. and ##/##) and every 3x3.
+    assert!(!validation.is_ok());
+    assert_eq!(&lines(&validation.errors)[..3], &[Some(5), Some(6), Some(7)]);
+    match validation.errors[1].error {
+      PatchError::InvalidRuleSize { input: 2, output: 4 } => {}
+      ref other => panic!("unexpected {:?}", other),
+    }
+    assert_eq!(validation.errors.len(), 3 + 3 + 102);
+    assert!(validation.errors[3..].iter().all(|d| d.line.is_none()));
+  }
+
+  #[test]
+  fn artwork_pixels() {
+    let mut art = Artwork::from_patch(&Patch::default());
+    assert_eq!(art.size(), 3);
+    assert_eq!(art.get(0, 1), Some(true));
+    assert_eq!(art.get(1, 0), Some(false));
+    assert_eq!(art.get(3, 0), None);
+    art.set(1, 0, true);
+    assert_eq!(art.on(), 6);
+    assert_eq!(art.on_in(1, 0, 2, 2), 3);
+    assert_eq!(art.on_in(2, 2, 5, 5), 1);
+    assert_eq!(art.on_by_block(2), vec![vec![2, 1], vec![2, 1]]);
+    assert_eq!(format!("{}", art), ".#.\n#.#\n###\n");
+
+    let empty = Artwork::new(0);
+    assert_eq!(format!("{}", empty), "");
+    assert_eq!(empty.on_by_block(2), Vec::<Vec<usize>>::new());
+    assert_eq!(art.on_by_block(0), Vec::<Vec<usize>>::new());
+  }
+
+  #[test]
+  fn assemble_artwork() {
+    let (small, large) = (Patch::new(2), Patch::default());
+    match Artwork::from_patches(&[]) {
+      Err(PatchError::NonSquareCount { count: 0 }) => {}
+      other => panic!("unexpected {:?}", other),
+    }
+    match Artwork::from_patches(&[&small, &small, &small]) {
+      Err(PatchError::NonSquareCount { count: 3 }) => {}
+      other => panic!("unexpected {:?}", other),
+    }
+    match Artwork::from_patches(&[&small, &small, &large, &small]) {
+      Err(PatchError::MismatchedPatch {
+        index: 2,
+        size: 3,
+        expected: 2,
+      }) => {}
+      other => panic!("unexpected {:?}", other),
+    }
+    let art = Artwork::from_patches(&[&large; 4]).unwrap();
+    assert_eq!(art.size(), 6);
+    assert_eq!(art.on(), 20);
+  }
+
+  #[test]
+  fn artwork_generations() {
+    let mut rules = PatternSet::new();
+    rules.insert("../.# => ##./#../...").unwrap();
+    rules.insert(".#./..#/### => #..#/..../..../#..#").unwrap();
+    let rulebook = Rulebook::new(&rules).unwrap();
+
+    let generations = rulebook
+      .generations(&Patch::default())
+      .take(3)
+      .collect::<Result<Vec<_>>>()
+      .unwrap();
+    assert_eq!(
+      format!("{}", generations[2]),
+      "##.##.\n#..#..\n......\n##.##.\n#..#..\n......\n"
+    );
+
+    let patches = rules.iter().unwrap().nth(1).unwrap().unwrap();
+    assert_eq!(Artwork::from_patches(&patches).unwrap(), generations[2]);
+    assert_eq!(generations[2].on_by_block(3), vec![vec![3, 3], vec![3, 3]]);
+
+    let mut image = Vec::new();
+    netpbm::write(&generations[1], &netpbm::Monochrome, netpbm::Format::Pbm, &mut image).unwrap();
+    assert_eq!(image, b"P4\n4 4\n\x90\x00\x00\x90".to_vec());
+  }
+
+  #[test]
+  fn pack_patches() {
+    let patch: Patch = ".#./..#/###".parse().unwrap();
+    let bits = pack(3, |row, col| patch.get((row, col)));
+    assert_eq!(bits, 0b111_100_010);
+    assert_eq!(unpack(3, bits), patch);
+    for variant in patch.variants() {
+      let variant_bits = pack(3, |row, col| variant.get((row, col)));
+      assert_eq!(canonical(3, variant_bits), canonical(3, bits));
+    }
+    assert_ne!(canonical(2, 0b0001), canonical(2, 0b0011));
+  }
+
+  #[test]
+  fn example_rulebook() {
+    let mut rules = PatternSet::new();
+    rules.insert("../.# => ##./#../...").unwrap();
+    rules.insert(".#./..#/### => #..#/..../..../#..#").unwrap();
+    let rulebook = Rulebook::new(&rules).unwrap();
+    assert_eq!(rulebook.count_on(&Patch::default(), 0).unwrap(), 5);
+    assert_eq!(rulebook.count_on(&Patch::default(), 1).unwrap(), 4);
+    assert_eq!(rulebook.count_on(&Patch::default(), 2).unwrap(), 12);
+    match rulebook.count_on(&Patch::default(), 3) {
+      Err(PatchError::MissingRule { .. }) => {}
+      other => panic!("unexpected {:?}", other.map(|_| ())),
+    }
+  }
+
+  /// A block in rule notation, like `#./..`.
+  fn notation(size: usize, bits: u16) -> String {
+    (0..size)
+      .map(|row| {
+        (0..size)
+          .map(|col| if bits & (1 << (row * size + col)) != 0 { '#' } else { '.' })
+          .collect::<String>()
+      })
+      .collect::<Vec<_>>()
+      .join("/")
+  }
+
+  /// Rules which cover every block, with outputs
+  /// scrambled from the input so the images don't
+  /// settle into a simple pattern.
+  fn covering_rules() -> PatternSet {
+    let mut rules = PatternSet::new();
+    for &size in [2, 3].iter() {
+      for bits in 0..1 << (size * size) {
+        if canonical(size, bits) == bits {
+          let mask = (1u32 << ((size + 1) * (size + 1))) - 1;
+          let output = ((bits as u32 * 37 + 1) & mask) as u16;
+          let rule = format!("{} => {}", notation(size, bits), notation(size + 1, output));
+          rules.insert(&rule).unwrap();
+        }
+      }
+    }
+    rules
+  }
+
+  #[test]
+  fn counting_overflow() {
+    // Every block becomes all on, so after 3k + 1 iterations
+    // from a 3x3 block, the image is 4 * 3^k cells across.
+    let mut rules = PatternSet::new();
+    for &size in [2, 3].iter() {
+      for bits in 0..1 << (size * size) {
+        if canonical(size, bits) == bits {
+          let output = ((1u32 << ((size + 1) * (size + 1))) - 1) as u16;
+          let rule = format!("{} => {}", notation(size, bits), notation(size + 1, output));
+          rules.insert(&rule).unwrap();
+        }
+      }
+    }
+    let rulebook = Rulebook::new(&rules).unwrap();
+    assert_eq!(
+      rulebook.count_on(&Patch::default(), 118).unwrap(),
+      16 * 3u128.pow(78)
+    );
+    match rulebook.count_on(&Patch::default(), 119) {
+      Err(PatchError::CountOverflow { iterations: 119 }) => {}
+      other => panic!("unexpected {:?}", other),
+    }
+  }
+
+  #[test]
+  fn rulebook_conflicts() {
+    let mut rules = PatternSet::new();
+    rules
+      .patterns
+      .insert("#./..".parse().unwrap(), "##./#../...".parse().unwrap());
+    rules
+      .patterns
+      .insert(".#/..".parse().unwrap(), "##./##./...".parse().unwrap());
+    match Rulebook::new(&rules) {
+      Err(PatchError::ConflictingRule { .. }) => {}
+      other => panic!("unexpected {:?}", other.map(|_| ())),
+    }
+  }
+
+  #[test]
+  fn counting_matches_iteration() {
+    let rules = covering_rules();
+    let rulebook = Rulebook::new(&rules).unwrap();
+    for n in 1..8 {
+      let patches = after_n(&rules, n).unwrap();
+      assert_eq!(
+        rulebook.count_on(&Patch::default(), n).unwrap(),
+        on(&patches) as u128,
+        "after {} iterations",
+        n
+      );
+    }
+  }
+
   use test::Bencher;
 
   #[test]
@@ -1,18 +1,87 @@
 use std::collections::HashMap;
 use std::str::FromStr;
-use std::marker::PhantomData;
+
+use super::super::netpbm;
 
 #[derive(Debug, Fail)]
 pub enum VirusError {
   #[fail(display = "Failure to parse cluster: {}", _0)] ParseError(String),
+
+  #[fail(display = "Unknown turn '{}', expected L, R, N or U", _0)] UnknownTurn(char),
+
+  #[fail(display = "A virus needs at least the clean and infected states, found {}", _0)]
+  TooFewStates(usize),
+
+  #[fail(display = "Rule for state {} moves to state {}, but there are only {} states", state,
+         next, states)]
+  InvalidState {
+    state: usize,
+    next: usize,
+    states: usize,
+  },
+
+  #[fail(display = "The cluster has a node in state {}, but the virus only has {} states", state,
+         states)]
+  UnknownState { state: usize, states: usize },
+}
+
+/// The named states of a node in the cluster, for the two
+/// puzzle viruses. Nodes are stored by state number: 0 is
+/// always clean and 1 is always infected, so that the initial
+/// map means the same thing to every virus.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Infection {
+  Clean = 0,
+  Infected = 1,
+  Flagged = 2,
+  Weakened = 3,
+}
+
+impl Infection {
+  /// The number of this state.
+  pub fn state(self) -> usize {
+    self as usize
+  }
+
+  /// The named state with this number, if there is one.
+  pub fn from_state(state: usize) -> Option<Infection> {
+    match state {
+      0 => Some(Infection::Clean),
+      1 => Some(Infection::Infected),
+      2 => Some(Infection::Flagged),
+      3 => Some(Infection::Weakened),
+      _ => None,
+    }
+  }
+}
+
+/// A turn the virus makes before it moves.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Turn {
+  Left,
+  Right,
+  /// Keep going the same way.
+  Straight,
+  Reverse,
+}
+
+impl Turn {
+  fn apply(self, direction: &Point) -> Point {
+    match self {
+      Turn::Left => direction.left(),
+      Turn::Right => direction.right(),
+      Turn::Straight => *direction,
+      Turn::Reverse => direction.reverse(),
+    }
+  }
 }
 
+/// What the virus does on a node in some state:
+/// it turns, then leaves the node in the `next` state.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
-enum Infection {
-  Clean,
-  Weakened,
-  Infected,
-  Flagged,
+pub struct Rule {
+  pub turn: Turn,
+  pub next: usize,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
@@ -63,7 +132,8 @@ impl Point {
 
 #[derive(Debug)]
 pub struct Cluster {
-  infected: HashMap<Point, Infection>,
+  /// The state of every node which isn't clean.
+  infected: HashMap<Point, usize>,
 }
 
 impl Cluster {
@@ -73,22 +143,103 @@ impl Cluster {
     }
   }
 
-  pub fn travel<'a>(&'a mut self) -> VirusIterator<'a, SimpleVirusMode> {
-    VirusIterator {
+  /// The state of the node at `(x, y)`, where
+  /// the origin is the center of the initial map
+  /// and `y` increases upwards.
+  pub fn state(&self, x: isize, y: isize) -> usize {
+    self.infected.get(&Point { x, y }).cloned().unwrap_or(0)
+  }
+
+  /// The named state of the node at `(x, y)`, as used by
+  /// the puzzle viruses.
+  ///
+  /// # Panics
+  ///
+  /// Panics if the node is in a state beyond the named ones,
+  /// left by a virus with more states; see `try_get`.
+  pub fn get(&self, x: isize, y: isize) -> Infection {
+    self.try_get(x, y).expect("Node state has no name")
+  }
+
+  /// The named state of the node at `(x, y)`, or
+  /// `None` for states beyond those.
+  pub fn try_get(&self, x: isize, y: isize) -> Option<Infection> {
+    Infection::from_state(self.state(x, y))
+  }
+
+  /// A square view of the cluster, extending
+  /// `radius` nodes out from the origin.
+  pub fn window<'a>(&'a self, radius: usize) -> ClusterWindow<'a> {
+    ClusterWindow {
       cluster: self,
-      direction: Point::up(),
-      position: Point::origin(),
-      mode: PhantomData,
+      radius: radius as isize,
     }
   }
 
+  /// Release the part 1 virus.
+  ///
+  /// # Panics
+  ///
+  /// Panics if the part 2 virus has already left nodes in states
+  /// the part 1 virus has no rule for; see `travel_with`.
+  pub fn travel<'a>(&'a mut self) -> VirusIterator<'a, SimpleVirusMode> {
+    self
+      .travel_with(SimpleVirusMode)
+      .expect("Cluster has states the simple virus doesn't know")
+  }
+
+  /// Release the part 2 virus, which has a rule for every state
+  /// either puzzle virus leaves behind.
   pub fn advnaced_travel<'a>(&'a mut self) -> VirusIterator<'a, AdvancedVirusMode> {
-    VirusIterator {
+    self
+      .travel_with(AdvancedVirusMode)
+      .expect("Cluster has states the advanced virus doesn't know")
+  }
+
+  /// Release a virus which follows the rules of `mode`. The rules
+  /// must be valid, and there must be a rule for every state in
+  /// the cluster, which may not be true if another virus has
+  /// already travelled through it.
+  pub fn travel_with<'a, T>(&'a mut self, mode: T) -> Result<VirusIterator<'a, T>, VirusError>
+  where
+    T: VirusMode,
+  {
+    let states = mode.rules().len();
+    check_rules(mode.rules())?;
+    if let Some(&state) = self.infected.values().find(|&&state| state >= states) {
+      return Err(VirusError::UnknownState { state, states });
+    }
+    Ok(VirusIterator {
       cluster: self,
       direction: Point::up(),
       position: Point::origin(),
-      mode: PhantomData,
-    }
+      mode,
+    })
+  }
+}
+
+/// A fixed-size view of a cluster, for drawing
+/// the (unbounded) cluster as an image.
+pub struct ClusterWindow<'a> {
+  cluster: &'a Cluster,
+  radius: isize,
+}
+
+impl<'a> netpbm::Grid for ClusterWindow<'a> {
+  type Cell = usize;
+
+  fn width(&self) -> usize {
+    (2 * self.radius + 1) as usize
+  }
+
+  fn height(&self) -> usize {
+    (2 * self.radius + 1) as usize
+  }
+
+  fn cell(&self, x: usize, y: usize) -> usize {
+    self
+      .cluster
+      .state(x as isize - self.radius, self.radius - y as isize)
   }
 }
 
@@ -107,7 +258,7 @@ impl FromStr for Cluster {
             x: (x as isize) - l,
             y: l - (y as isize),
           },
-          Infection::Infected,
+          Infection::Infected.state(),
         );
       }
     }
@@ -116,11 +267,134 @@ impl FromStr for Cluster {
   }
 }
 
-pub trait VirusMode {}
-pub struct AdvancedVirusMode;
-impl VirusMode for AdvancedVirusMode {}
+/// The rules a virus follows, as a table with a rule for each
+/// node state, by state number. State 0 is clean, and nodes
+/// which the map marks with `#` start in state 1, infected.
+pub trait VirusMode {
+  fn rules(&self) -> &[Rule];
+}
+
+/// The part 1 virus, which toggles nodes between clean and infected.
 pub struct SimpleVirusMode;
-impl VirusMode for SimpleVirusMode {}
+
+const SIMPLE_RULES: [Rule; 2] = [
+  Rule {
+    turn: Turn::Left,
+    next: 1,
+  },
+  Rule {
+    turn: Turn::Right,
+    next: 0,
+  },
+];
+
+impl VirusMode for SimpleVirusMode {
+  fn rules(&self) -> &[Rule] {
+    &SIMPLE_RULES
+  }
+}
+
+/// The part 2 virus, which weakens clean nodes before infecting
+/// them, and flags infected nodes before cleaning them.
+pub struct AdvancedVirusMode;
+
+const ADVANCED_RULES: [Rule; 4] = [
+  // Clean
+  Rule {
+    turn: Turn::Left,
+    next: 3,
+  },
+  // Infected
+  Rule {
+    turn: Turn::Right,
+    next: 2,
+  },
+  // Flagged
+  Rule {
+    turn: Turn::Reverse,
+    next: 0,
+  },
+  // Weakened
+  Rule {
+    turn: Turn::Straight,
+    next: 1,
+  },
+];
+
+impl VirusMode for AdvancedVirusMode {
+  fn rules(&self) -> &[Rule] {
+    &ADVANCED_RULES
+  }
+}
+
+/// A virus with any rules, checked when the table is built.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RuleTable {
+  rules: Vec<Rule>,
+}
+
+impl RuleTable {
+  pub fn new(rules: Vec<Rule>) -> Result<Self, VirusError> {
+    check_rules(&rules)?;
+    Ok(RuleTable { rules })
+  }
+}
+
+/// Check that there are rules for at least the clean and
+/// infected states, and that every rule moves to a known state.
+fn check_rules(rules: &[Rule]) -> Result<(), VirusError> {
+  if rules.len() < 2 {
+    return Err(VirusError::TooFewStates(rules.len()));
+  }
+  for (state, rule) in rules.iter().enumerate() {
+    if rule.next >= rules.len() {
+      return Err(VirusError::InvalidState {
+        state,
+        next: rule.next,
+        states: rules.len(),
+      });
+    }
+  }
+  Ok(())
+}
+
+impl VirusMode for RuleTable {
+  fn rules(&self) -> &[Rule] {
+    &self.rules
+  }
+}
+
+/// Parse a Langton's ant style rule, with one turn per state:
+/// `L`eft, `R`ight, `N`o turn or `U`-turn. Each state moves on
+/// to the next, and the last back to clean, so the part 1
+/// virus is `LR`.
+impl FromStr for RuleTable {
+  type Err = VirusError;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    let turns = s.trim()
+      .chars()
+      .map(|c| match c.to_ascii_uppercase() {
+        'L' => Ok(Turn::Left),
+        'R' => Ok(Turn::Right),
+        'N' => Ok(Turn::Straight),
+        'U' => Ok(Turn::Reverse),
+        _ => Err(VirusError::UnknownTurn(c)),
+      })
+      .collect::<Result<Vec<_>, _>>()?;
+    let states = turns.len();
+    RuleTable::new(
+      turns
+        .into_iter()
+        .enumerate()
+        .map(|(state, turn)| Rule {
+          turn,
+          next: (state + 1) % states.max(1),
+        })
+        .collect(),
+    )
+  }
+}
 
 pub struct VirusIterator<'a, T>
 where
@@ -129,89 +403,46 @@ where
   cluster: &'a mut Cluster,
   direction: Point,
   position: Point,
-  mode: PhantomData<T>,
+  mode: T,
 }
 
-impl<'a> Iterator for VirusIterator<'a, SimpleVirusMode> {
-  type Item = bool;
-
-  fn next(&mut self) -> Option<bool> {
-    // 1. Turn the virus.
-    // 2. Toggle the position.
-    let result = if self.cluster.infected.contains_key(&self.position) {
-      self.direction = self.direction.right();
-      self.cluster.infected.remove(&self.position);
-      Some(false)
-    } else {
-      self.direction = self.direction.left();
-      self
-        .cluster
-        .infected
-        .insert(self.position, Infection::Infected);
-      Some(true)
-    };
-
-    // 3. Move the virus
-    self.position.travel(&self.direction);
-
-    // 4. Yeild the desired value.
-    result
+impl<'a, T> VirusIterator<'a, T>
+where
+  T: VirusMode,
+{
+  /// The cluster in its current state.
+  pub fn cluster(&self) -> &Cluster {
+    self.cluster
   }
 }
 
-impl<'a> Iterator for VirusIterator<'a, AdvancedVirusMode> {
+/// Each burst yields whether the virus infected the node it was on.
+impl<'a, T> Iterator for VirusIterator<'a, T>
+where
+  T: VirusMode,
+{
   type Item = bool;
 
   fn next(&mut self) -> Option<bool> {
-    use std::collections::hash_map::Entry::Occupied;
-
     // 1. Turn the virus.
-    // 2. Toggle the position.
-    let result;
-    {
-      let state = self
-        .cluster
-        .infected
-        .entry(self.position)
-        .or_insert(Infection::Clean);
-      result = match *state {
-        Infection::Clean => {
-          self.direction = self.direction.left();
-          *state = Infection::Weakened;
-          Some(false)
-        }
-        Infection::Weakened => {
-          *state = Infection::Infected;
-          Some(true)
-        }
-        Infection::Infected => {
-          self.direction = self.direction.right();
-          *state = Infection::Flagged;
-          Some(false)
-        }
-        Infection::Flagged => {
-          self.direction = self.direction.reverse();
-          *state = Infection::Clean;
-          Some(false)
-        }
-      };
+    let state = self.cluster.state(self.position.x, self.position.y);
+    // `travel_with` checked there is a rule for every
+    // state in the cluster, and every state a rule leads to.
+    let rule = self.mode.rules()[state];
+    self.direction = rule.turn.apply(&self.direction);
+
+    // 2. Update the node.
+    if rule.next == Infection::Clean.state() {
+      self.cluster.infected.remove(&self.position);
+    } else {
+      self.cluster.infected.insert(self.position, rule.next);
     }
 
-    match self.cluster.infected.entry(self.position) {
-      Occupied(entry) => {
-        if entry.get() == &Infection::Clean {
-          entry.remove();
-        }
-      }
-      _ => {}
-    };
-
     // 3. Move the virus
     self.position.travel(&self.direction);
 
-    // println!("{:?}", self.cluster.infected);
     // 4. Yeild the desired value.
-    result
+    Some(rule.next == Infection::Infected.state())
   }
 }
 
@@ -256,6 +487,18 @@ mod test {
     );
   }
 
+  #[test]
+  fn cluster_window() {
+    use netpbm::Grid;
+
+    let cluster: Cluster = "..#\n#..\n...".parse().unwrap();
+    let window = cluster.window(2);
+    assert_eq!(window.width(), 5);
+    assert_eq!(window.cell(3, 1), Infection::Infected.state());
+    assert_eq!(window.cell(1, 2), Infection::Infected.state());
+    assert_eq!(window.cell(2, 2), Infection::Clean.state());
+  }
+
   #[test]
   fn advanced_cluster_example() {
     let mut cluster: Cluster = "..#\n#..\n...".parse().unwrap();
@@ -268,4 +511,121 @@ mod test {
       26
     );
   }
+
+  #[test]
+  fn rule_table_matches_modes() {
+    let simple: RuleTable = "LR".parse().unwrap();
+    assert_eq!(simple.rules(), SimpleVirusMode.rules());
+
+    let advanced = RuleTable::new(AdvancedVirusMode.rules().to_vec()).unwrap();
+    let mut cluster: Cluster = "..#\n#..\n...".parse().unwrap();
+    assert_eq!(
+      cluster
+        .travel_with(advanced)
+        .unwrap()
+        .take(100)
+        .map(|x| x as usize)
+        .sum::<usize>(),
+      26
+    );
+    assert_eq!(cluster.state(0, 0), Infection::Infected.state());
+  }
+
+  #[test]
+  fn langtons_ant() {
+    // The classic ant, starting on an empty grid, takes
+    // about 10,000 steps before it starts building a highway,
+    // which repeats every 104 steps and moves 2 nodes away.
+    let mut cluster = Cluster::new();
+    let mut ant = cluster
+      .travel_with("RL".parse::<RuleTable>().unwrap())
+      .unwrap();
+    for _ in 0..11000 {
+      ant.next();
+    }
+    let (before, count) = (ant.position, ant.cluster().infected.len());
+    for _ in 0..104 {
+      ant.next();
+    }
+    assert_eq!((ant.position.x - before.x).abs() + (ant.position.y - before.y).abs(), 4);
+    assert_eq!(ant.cluster().infected.len(), count + 12);
+  }
+
+  #[test]
+  fn reused_cluster() {
+    let mut cluster: Cluster = "..#\n#..\n...".parse().unwrap();
+    cluster.advnaced_travel().nth(0);
+    assert_eq!(cluster.get(0, 0), Infection::Weakened);
+    assert_eq!(cluster.try_get(0, 0), Some(Infection::Weakened));
+    match cluster.travel_with(SimpleVirusMode) {
+      Err(VirusError::UnknownState {
+        state: 3,
+        states: 2,
+      }) => {}
+      other => panic!("unexpected {:?}", other.map(|_| ())),
+    }
+    assert!(cluster.travel_with(AdvancedVirusMode).is_ok());
+
+    struct Broken;
+    impl VirusMode for Broken {
+      fn rules(&self) -> &[Rule] {
+        &SIMPLE_RULES[..1]
+      }
+    }
+    match Cluster::new().travel_with(Broken) {
+      Err(VirusError::TooFewStates(1)) => {}
+      other => panic!("unexpected {:?}", other.map(|_| ())),
+    }
+  }
+
+  #[test]
+  #[should_panic]
+  fn simple_travel_after_advanced() {
+    let mut cluster: Cluster = "..#\n#..\n...".parse().unwrap();
+    cluster.advnaced_travel().nth(0);
+    cluster.travel();
+  }
+
+  #[test]
+  fn unnamed_states() {
+    let mut cluster = Cluster::new();
+    cluster
+      .travel_with("LRRL".parse::<RuleTable>().unwrap())
+      .unwrap()
+      .nth(0);
+    assert_eq!(cluster.state(0, 0), 1);
+    assert_eq!(cluster.get(0, 0), Infection::Infected);
+    cluster.infected.insert(Point::origin(), 4);
+    assert_eq!(cluster.try_get(0, 0), None);
+  }
+
+  #[test]
+  fn invalid_rule_tables() {
+    match "LRX".parse::<RuleTable>() {
+      Err(VirusError::UnknownTurn('X')) => {}
+      other => panic!("unexpected {:?}", other),
+    }
+    match "L".parse::<RuleTable>() {
+      Err(VirusError::TooFewStates(1)) => {}
+      other => panic!("unexpected {:?}", other),
+    }
+    let rules = vec![
+      Rule {
+        turn: Turn::Left,
+        next: 1,
+      },
+      Rule {
+        turn: Turn::Right,
+        next: 2,
+      },
+    ];
+    match RuleTable::new(rules) {
+      Err(VirusError::InvalidState {
+        state: 1,
+        next: 2,
+        states: 2,
+      }) => {}
+      other => panic!("unexpected {:?}", other),
+    }
+  }
 }
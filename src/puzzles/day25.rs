@@ -2,6 +2,7 @@ use std::collections::{HashSet, HashMap};
 use std::str::FromStr;
 use std::fmt;
 
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct TuringMachine {
   cursor: isize,
 
@@ -61,6 +62,15 @@ impl TuringMachine {
     }
   }
   
+  /// The machine after a single step, for use with
+  /// the `cycle` tools. Machines are only equal if their
+  /// tapes are, including where they are on the tape.
+  pub fn successor(&self) -> Result<TuringMachine, MachineError> {
+    let mut machine = self.clone();
+    machine.advance()?;
+    Ok(machine)
+  }
+
   fn advance(&mut self) -> Result<(), MachineError> {
     let state = self.states.get(&self.state).ok_or(MachineError::InvalidState)?;
     let rule = if self.tape.contains(&self.cursor) {&state.one} else {&state.zero};
@@ -139,7 +149,7 @@ impl From<ParseIntError> for ParseTuringError {
   }
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 enum Value {
   Zero,
   One,
@@ -157,7 +167,7 @@ impl FromStr for Value {
   }
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 enum Direction {
   Left,
   Right,
@@ -175,7 +185,7 @@ impl FromStr for Direction {
   }
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 struct Rule {
   value: Value,
   state: String,
@@ -208,7 +218,7 @@ impl FromStr for Rule {
   }
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 struct State {
   name: String,
   zero: Rule,
@@ -277,6 +287,7 @@ In state B:
     - Continue with state A.";
 
   use super::*;
+  use super::super::super::cycle;
 
   #[test]
   fn parse_rule() {
@@ -306,4 +317,40 @@ In state B:
     let mut tm : TuringMachine = RULES.parse().unwrap();
     assert_eq!(tm.iter().take(6).last(), Some(3));
   }
+
+  #[test]
+  fn machine_successor() {
+    let tm: TuringMachine = RULES.parse().unwrap();
+    let last = cycle::nth_state(&tm, |m| m.successor().unwrap(), 6);
+    assert_eq!(last.count(), 3);
+  }
+
+  static SHUTTLE: &str = "Begin in state A.
+Perform a diagnostic checksum after 4 steps.
+
+In state A:
+  If the current value is 0:
+    - Write the value 1.
+    - Move one slot to the right.
+    - Continue with state B.
+  If the current value is 1:
+    - Write the value 0.
+    - Move one slot to the right.
+    - Continue with state B.
+
+In state B:
+  If the current value is 0:
+    - Write the value 0.
+    - Move one slot to the left.
+    - Continue with state A.
+  If the current value is 1:
+    - Write the value 1.
+    - Move one slot to the left.
+    - Continue with state A.";
+
+  #[test]
+  fn machine_cycle() {
+    let tm: TuringMachine = SHUTTLE.parse().unwrap();
+    assert_eq!(cycle::brent(&tm, |m| m.successor().unwrap()), (0, 4));
+  }
 }
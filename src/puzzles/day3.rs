@@ -1,4 +1,4 @@
-use std::collections::HashMap;
+use std::cmp;
 
 #[allow(non_camel_case_types)]
 type int = i32;
@@ -12,7 +12,93 @@ pub struct MemoryPosition {
   y: int,
 }
 
+/// Integer square root, rounded down. The squares are
+/// checked as `u64`, as `(root + 1)^2` may not fit in a `uint`.
+fn isqrt(n: uint) -> uint {
+  let n = n as u64;
+  let mut root = (n as f64).sqrt() as u64;
+  while root * root > n {
+    root -= 1;
+  }
+  while (root + 1) * (root + 1) <= n {
+    root += 1;
+  }
+  root as uint
+}
+
 impl MemoryPosition {
+  pub fn new(x: int, y: int) -> MemoryPosition {
+    MemoryPosition { x, y }
+  }
+
+  pub fn x(&self) -> int {
+    self.x
+  }
+
+  pub fn y(&self) -> int {
+    self.y
+  }
+
+  /// Find the position of a square in the spiral, without
+  /// walking the spiral.
+  ///
+  /// Ring `k` of the spiral is the square of side `2k + 1`
+  /// around the origin, and holds the squares after `(2k - 1)^2`
+  /// up to `(2k + 1)^2`. Each ring is walked as four sides of
+  /// length `2k`, starting just above the bottom right corner.
+  ///
+  /// Squares are numbered from 1, so there is no square 0;
+  /// like square 1, it is placed at the origin.
+  pub fn from_square(square: uint) -> MemoryPosition {
+    if square <= 1 {
+      return MemoryPosition { x: 0, y: 0 };
+    }
+    let ring = (isqrt(square - 1) + 1) / 2;
+    let offset = square - (2 * ring - 1) * (2 * ring - 1) - 1;
+    let side = offset / (2 * ring);
+    let along = (offset % (2 * ring)) as int;
+    let k = ring as int;
+    match side {
+      0 => MemoryPosition {
+        x: k,
+        y: along - k + 1,
+      },
+      1 => MemoryPosition {
+        x: k - 1 - along,
+        y: k,
+      },
+      2 => MemoryPosition {
+        x: -k,
+        y: k - 1 - along,
+      },
+      _ => MemoryPosition {
+        x: along - k + 1,
+        y: -k,
+      },
+    }
+  }
+
+  /// The number of the square at this position in the spiral.
+  /// This is the inverse of `from_square`.
+  pub fn square(&self) -> uint {
+    let k = cmp::max(self.x.abs(), self.y.abs());
+    if k == 0 {
+      return 1;
+    }
+    let side = (2 * k - 1) as uint;
+    let start = side * side;
+    let offset = if self.x == k && self.y > -k {
+      self.y + k - 1
+    } else if self.y == k {
+      2 * k + k - 1 - self.x
+    } else if self.x == -k {
+      4 * k + k - 1 - self.y
+    } else {
+      6 * k + self.x + k - 1
+    };
+    start + 1 + offset as uint
+  }
+
   fn shift(&self, dx: int, dy: int) -> MemoryPosition {
     MemoryPosition {
       x: self.x + dx,
@@ -25,64 +111,172 @@ impl MemoryPosition {
   }
 }
 
-fn neighbor_sum(
-  position: &MemoryPosition,
-  grid: &HashMap<MemoryPosition, uint>,
-) -> Result<uint, String> {
-  let mut nsum: uint = 0;
-  for dx in -1..2 {
-    for dy in -1..2 {
-      nsum = nsum
-        .checked_add(*grid.get(&position.shift(dx, dy)).unwrap_or(&0))
-        .ok_or("Overflow Error")?;
-    }
-  }
-  Ok(nsum)
+/// Values which can be stored in a spiral fill.
+/// Arithmetic is checked, so that rules can stop
+/// the fill when values overflow.
+pub trait SpiralValue: Copy {
+  fn zero() -> Self;
+  fn one() -> Self;
+  fn checked_add(self, other: Self) -> Option<Self>;
+  fn checked_mul(self, other: Self) -> Option<Self>;
 }
 
-pub fn first_large_value(position: uint) -> Result<uint, String> {
-  let mut dd = (1, 0);
-  let mut grid: HashMap<MemoryPosition, uint> = HashMap::new();
-  let mut pos = MemoryPosition { x: 0, y: 0 };
-  grid.insert(pos, 1);
-  for _i in 1.. {
-    pos = pos.shift(dd.0, dd.1);
-    let val = neighbor_sum(&pos, &grid)?;
-    grid.insert(pos, val);
-    if !grid.contains_key(&MemoryPosition {
-      x: pos.x - dd.1,
-      y: pos.y + dd.0,
-    }) {
-      dd = (-1 * dd.1, dd.0)
+macro_rules! spiral_value {
+  ($t:ty) => {
+    impl SpiralValue for $t {
+      fn zero() -> Self {
+        0
+      }
+
+      fn one() -> Self {
+        1
+      }
+
+      fn checked_add(self, other: Self) -> Option<Self> {
+        <$t>::checked_add(self, other)
+      }
+
+      fn checked_mul(self, other: Self) -> Option<Self> {
+        <$t>::checked_mul(self, other)
+      }
     }
-    if val > position {
-      return Ok(val);
+  };
+}
+
+spiral_value!(u32);
+spiral_value!(u64);
+spiral_value!(u128);
+
+/// The squares of the spiral which have been filled so far.
+pub struct Filled<T> {
+  values: Vec<T>,
+}
+
+impl<T> Filled<T> {
+  /// The value at a position, if it has been filled.
+  pub fn get(&self, position: &MemoryPosition) -> Option<&T> {
+    self.values.get(position.square() as usize - 1)
+  }
+
+  /// The filled values among the eight surrounding squares.
+  pub fn moore<'a>(&'a self, position: &MemoryPosition) -> Vec<&'a T> {
+    let mut values = Vec::with_capacity(8);
+    for dx in -1..2 {
+      for dy in -1..2 {
+        if dx != 0 || dy != 0 {
+          values.extend(self.get(&position.shift(dx, dy)));
+        }
+      }
     }
+    values
+  }
+
+  /// The filled values among the four orthogonally adjacent squares.
+  pub fn von_neumann<'a>(&'a self, position: &MemoryPosition) -> Vec<&'a T> {
+    [(1, 0), (0, 1), (-1, 0), (0, -1)]
+      .iter()
+      .filter_map(|&(dx, dy)| self.get(&position.shift(dx, dy)))
+      .collect()
   }
-  return Err(format!("No value above {} found", position));
 }
 
-pub fn memory_distance(position: uint) -> uint {
-  let mut dd = (1, 0);
-  let mut grid: HashMap<MemoryPosition, uint> = HashMap::new();
-  let mut pos = MemoryPosition { x: 0, y: 0 };
-  grid.insert(pos, 1);
-  for _i in 1..position {
-    pos = pos.shift(dd.0, dd.1);
-    grid.insert(pos, 1);
-    if !grid.contains_key(&MemoryPosition {
-      x: pos.x - dd.1,
-      y: pos.y + dd.0,
-    }) {
-      dd = (-1 * dd.1, dd.0)
+/// The sum of the values in the eight surrounding squares.
+pub fn neighbor_sum<T>(position: &MemoryPosition, filled: &Filled<T>) -> Option<T>
+where
+  T: SpiralValue,
+{
+  filled
+    .moore(position)
+    .iter()
+    .try_fold(T::zero(), |acc, &&v| acc.checked_add(v))
+}
+
+/// The product of the values in the eight surrounding squares.
+pub fn neighbor_product<T>(position: &MemoryPosition, filled: &Filled<T>) -> Option<T>
+where
+  T: SpiralValue,
+{
+  filled
+    .moore(position)
+    .iter()
+    .try_fold(T::one(), |acc, &&v| acc.checked_mul(v))
+}
+
+/// The sum of the values in the four orthogonally adjacent squares.
+pub fn von_neumann_sum<T>(position: &MemoryPosition, filled: &Filled<T>) -> Option<T>
+where
+  T: SpiralValue,
+{
+  filled
+    .von_neumann(position)
+    .iter()
+    .try_fold(T::zero(), |acc, &&v| acc.checked_add(v))
+}
+
+/// Fills the spiral one square at a time, starting with a seed
+/// value in square 1. Each later value is computed by a rule from
+/// the position and the squares filled so far. The fill stops
+/// when the rule returns `None`, e.g. when a value overflows.
+///
+/// Yields `(square, position, value)` for each square.
+pub struct SpiralFill<T, F> {
+  filled: Filled<T>,
+  seed: Option<T>,
+  rule: F,
+}
+
+impl<T, F> SpiralFill<T, F>
+where
+  T: Copy,
+  F: FnMut(&MemoryPosition, &Filled<T>) -> Option<T>,
+{
+  pub fn new(seed: T, rule: F) -> Self {
+    SpiralFill {
+      filled: Filled { values: Vec::new() },
+      seed: Some(seed),
+      rule,
     }
   }
-  return pos.mhdistance();
+}
+
+impl<T, F> Iterator for SpiralFill<T, F>
+where
+  T: Copy,
+  F: FnMut(&MemoryPosition, &Filled<T>) -> Option<T>,
+{
+  type Item = (uint, MemoryPosition, T);
+
+  fn next(&mut self) -> Option<Self::Item> {
+    let square = self.filled.values.len() as uint + 1;
+    let position = MemoryPosition::from_square(square);
+    let value = match self.seed.take() {
+      Some(seed) => seed,
+      None => (self.rule)(&position, &self.filled)?,
+    };
+    self.filled.values.push(value);
+    Some((square, position, value))
+  }
+}
+
+/// The first value written by the stress test
+/// which is larger than the puzzle input.
+pub fn first_large_value(position: uint) -> Result<uint, String> {
+  SpiralFill::new(1, neighbor_sum)
+    .map(|(_square, _position, value)| value)
+    .find(|&value| value > position)
+    .ok_or_else(|| format!("No value above {} found", position))
+}
+
+/// The number of steps to carry data from
+/// a square back to the access port at square 1.
+pub fn memory_distance(position: uint) -> uint {
+  MemoryPosition::from_square(position).mhdistance()
 }
 
 #[cfg(test)]
 mod tests {
   use super::*;
+  use std::collections::HashMap;
 
   #[test]
   fn simple_grid() {
@@ -92,6 +286,110 @@ mod tests {
     assert_eq!(memory_distance(1024), 31);
   }
 
+  /// Find the positions of squares 1 to `squares` by walking the spiral.
+  fn walk(squares: uint) -> Vec<MemoryPosition> {
+    let mut dd = (1, 0);
+    let mut grid: HashMap<MemoryPosition, uint> = HashMap::new();
+    let mut pos = MemoryPosition { x: 0, y: 0 };
+    grid.insert(pos, 1);
+    let mut positions = vec![pos];
+    for _i in 1..squares {
+      pos = pos.shift(dd.0, dd.1);
+      grid.insert(pos, 1);
+      positions.push(pos);
+      if !grid.contains_key(&MemoryPosition {
+        x: pos.x - dd.1,
+        y: pos.y + dd.0,
+      }) {
+        dd = (-1 * dd.1, dd.0)
+      }
+    }
+    positions
+  }
+
+  #[test]
+  fn closed_form_matches_walk() {
+    for (square, pos) in (1..).zip(walk(20000)) {
+      assert_eq!(MemoryPosition::from_square(square), pos, "square {}", square);
+      assert_eq!(pos.square(), square, "position {:?}", pos);
+    }
+  }
+
   #[test]
-  fn first_val() {}
+  fn closed_form_matches_walk_sampled() {
+    // A cheap pseudo-random sample of larger squares.
+    let mut square: uint = 12345;
+    for _ in 0..10 {
+      square = (square * 7919 + 104729) % 400000;
+      let pos = *walk(square).last().unwrap();
+      assert_eq!(MemoryPosition::from_square(square), pos);
+      assert_eq!(pos.square(), square);
+    }
+  }
+
+  #[test]
+  fn square_roots() {
+    let large = uint::max_value() - 10000..=uint::max_value();
+    for n in (0..10000).chain(large) {
+      let (root, n) = (isqrt(n) as u64, n as u64);
+      assert!(root * root <= n && (root + 1) * (root + 1) > n);
+    }
+  }
+
+  #[test]
+  fn largest_squares() {
+    for square in uint::max_value() - 1000..=uint::max_value() {
+      assert_eq!(MemoryPosition::from_square(square).square(), square);
+    }
+    assert_eq!(memory_distance(uint::max_value()), 65534);
+  }
+
+  #[test]
+  fn first_val() {
+    assert_eq!(first_large_value(1), Ok(2));
+    assert_eq!(first_large_value(10), Ok(11));
+    assert_eq!(first_large_value(747), Ok(806));
+  }
+
+  #[test]
+  fn stress_test_values() {
+    let values: Vec<u64> = SpiralFill::new(1, neighbor_sum)
+      .map(|(_, _, v)| v)
+      .take(23)
+      .collect();
+    assert_eq!(
+      values,
+      vec![
+        1, 1, 2, 4, 5, 10, 11, 23, 25, 26, 54, 57, 59, 122, 133, 142, 147, 304, 330, 351, 362,
+        747, 806,
+      ]
+    );
+  }
+
+  #[test]
+  fn fill_positions() {
+    for (square, position, _) in SpiralFill::new(1u32, neighbor_sum).take(100) {
+      assert_eq!(position, MemoryPosition::from_square(square));
+    }
+  }
+
+  #[test]
+  fn von_neumann_values() {
+    let values: Vec<u32> = SpiralFill::new(1, von_neumann_sum)
+      .map(|(_, _, v)| v)
+      .take(9)
+      .collect();
+    assert_eq!(values, vec![1, 1, 1, 2, 2, 3, 3, 4, 5]);
+  }
+
+  #[test]
+  fn fill_until_overflow() {
+    let small = SpiralFill::new(1u32, neighbor_sum).count();
+    let medium = SpiralFill::new(1u64, neighbor_sum).count();
+    let large = SpiralFill::new(1u128, neighbor_sum).count();
+    assert!(small < medium && medium < large);
+
+    let products = SpiralFill::new(2u64, neighbor_product).count();
+    assert!(products < small);
+  }
 }
@@ -1,26 +1,284 @@
-// Make a single jump.
-pub fn jump_instruction(instructions: &mut Vec<isize>, position: isize) -> Result<isize, String> {
-  let jump = instructions[position as usize];
-  instructions[position as usize] += 1;
-  if jump < -position || position + jump > (instructions.len() as isize) {
-    return Err("Out of bounds.".to_string());
+//! Day 5: A Maze of Twisty Trampolines, All Alike
+//!
+//! The maze is a list of jump offsets. Each step jumps by the
+//! offset at the current position, then updates that offset
+//! with a rule, until the position leaves the list.
+
+use std::fmt;
+
+#[derive(Debug, Fail, Clone, PartialEq, Eq)]
+pub enum MazeError {
+  #[fail(display = "the offset {} at position {} can't be updated without overflowing", offset,
+         position)]
+  Overflow {
+    position: usize,
+    offset: i32,
+  },
+}
+
+/// How an offset changes after it has been jumped from.
+/// Returns `None` if the new offset would overflow.
+pub trait Rule {
+  fn update(&self, offset: i32) -> Option<i32>;
+}
+
+impl<F> Rule for F
+where
+  F: Fn(i32) -> i32,
+{
+  fn update(&self, offset: i32) -> Option<i32> {
+    Some(self(offset))
+  }
+}
+
+/// Part one: every offset increases by one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Increment;
+
+impl Rule for Increment {
+  fn update(&self, offset: i32) -> Option<i32> {
+    offset.checked_add(1)
   }
-  return Ok(position + jump);
 }
 
+/// Part two: offsets of three or more decrease by one,
+/// all others increase by one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Strange;
+
+impl Rule for Strange {
+  fn update(&self, offset: i32) -> Option<i32> {
+    if offset >= 3 {
+      offset.checked_sub(1)
+    } else {
+      offset.checked_add(1)
+    }
+  }
+}
+
+/// Why a maze run stopped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Exit {
+  /// Jumped to a position before the first offset.
+  Front,
+
+  /// Jumped to a position after the last offset.
+  Back,
+
+  /// Ran for the step limit without leaving the maze.
+  StepLimit,
+}
+
+impl fmt::Display for Exit {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match *self {
+      Exit::Front => write!(f, "fell off the front"),
+      Exit::Back => write!(f, "fell off the back"),
+      Exit::StepLimit => write!(f, "reached the step limit"),
+    }
+  }
+}
+
+/// A single jump, as recorded in a trace.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Jump {
+  /// The position jumped from.
+  pub from: usize,
+
+  /// The offset used for the jump.
+  pub offset: i32,
+
+  /// The position jumped to, which may be outside the maze.
+  pub to: i64,
+}
+
+/// A jump maze, using `rule` to update offsets.
+pub struct Maze<R> {
+  offsets: Vec<i32>,
+  position: usize,
+  steps: u64,
+  rule: R,
+  trace: Option<Vec<Jump>>,
+  exit: Option<Exit>,
+}
+
+impl<R> Maze<R>
+where
+  R: Rule,
+{
+  pub fn new(offsets: Vec<i32>, rule: R) -> Self {
+    Maze {
+      offsets,
+      position: 0,
+      steps: 0,
+      rule,
+      trace: None,
+      exit: None,
+    }
+  }
+
+  /// Record every jump made by this maze.
+  pub fn with_trace(mut self) -> Self {
+    self.trace = Some(Vec::new());
+    self
+  }
+
+  /// The current offsets.
+  pub fn offsets(&self) -> &[i32] {
+    &self.offsets
+  }
+
+  /// The current position. Once the maze has been
+  /// exited this is the last position inside the maze.
+  pub fn position(&self) -> usize {
+    self.position
+  }
+
+  /// The number of jumps made so far, including
+  /// the jump which left the maze.
+  pub fn steps(&self) -> u64 {
+    self.steps
+  }
+
+  /// The recorded jumps, if tracing is enabled.
+  pub fn trace(&self) -> Option<&[Jump]> {
+    self.trace.as_deref()
+  }
+
+  /// How the maze was exited, if it has been.
+  pub fn exit(&self) -> Option<Exit> {
+    self.exit
+  }
+
+  /// Make a single jump, returning how the maze was exited if
+  /// the jump left it. Once the maze has been exited, this makes
+  /// no more jumps and returns the same exit again. An offset
+  /// which would overflow is an error, and leaves the maze as
+  /// it was.
+  pub fn step(&mut self) -> Result<Option<Exit>, MazeError> {
+    if self.exit.is_some() {
+      return Ok(self.exit);
+    }
+    if self.position >= self.offsets.len() {
+      self.exit = Some(Exit::Back);
+      return Ok(self.exit);
+    }
+    let from = self.position;
+    let offset = self.offsets[from];
+    self.offsets[from] = self
+      .rule
+      .update(offset)
+      .ok_or(MazeError::Overflow {
+        position: from,
+        offset,
+      })?;
+    self.steps += 1;
+
+    let to = from as i64 + offset as i64;
+    if let Some(ref mut trace) = self.trace {
+      trace.push(Jump { from, offset, to });
+    }
+    if to < 0 {
+      self.exit = Some(Exit::Front);
+    } else if to >= self.offsets.len() as i64 {
+      self.exit = Some(Exit::Back);
+    } else {
+      self.position = to as usize;
+    }
+    Ok(self.exit)
+  }
+
+  /// Jump until the maze is exited, or until `limit`
+  /// steps have been made in total if there is a limit.
+  pub fn run(&mut self, limit: Option<u64>) -> Result<Exit, MazeError> {
+    if self.trace.is_none() && limit.is_none() && self.exit.is_none() {
+      return self.run_fast();
+    }
+    loop {
+      if let Some(exit) = self.exit {
+        return Ok(exit);
+      }
+      if limit.is_some_and(|l| self.steps >= l) {
+        return Ok(Exit::StepLimit);
+      }
+      self.step()?;
+    }
+  }
+
+  /// The untraced, unlimited inner loop, which is
+  /// all that's needed for the puzzle itself.
+  fn run_fast(&mut self) -> Result<Exit, MazeError> {
+    let len = self.offsets.len() as i64;
+    let mut position = self.position as i64;
+    let mut steps = self.steps;
+    let result = loop {
+      if position < 0 {
+        break Ok(Exit::Front);
+      } else if position >= len {
+        break Ok(Exit::Back);
+      }
+      self.position = position as usize;
+      let offset = &mut self.offsets[position as usize];
+      match self.rule.update(*offset) {
+        Some(next) => {
+          position += *offset as i64;
+          *offset = next;
+          steps += 1;
+        }
+        None => {
+          break Err(MazeError::Overflow {
+            position: self.position,
+            offset: *offset,
+          })
+        }
+      }
+    };
+    self.steps = steps;
+    self.exit = result.as_ref().ok().cloned();
+    result
+  }
+}
+
+/// The number of steps needed to leave the maze.
+pub fn escape<R: Rule>(offsets: &[i32], rule: R) -> Result<u64, MazeError> {
+  let mut maze = Maze::new(offsets.to_vec(), rule);
+  maze.run(None)?;
+  Ok(maze.steps())
+}
+
+/// Make a single jump, using the part one rule. Jumping to just
+/// past the end is allowed, any further out of bounds is an error.
+/// Prefer `Maze`, which this is kept for.
+pub fn jump_instruction(instructions: &mut Vec<isize>, position: isize) -> Result<isize, String> {
+  jump_with(instructions, position, &Increment)
+}
+
+/// Make a single jump, using the part two rule.
 pub fn jump_fancy(instructions: &mut Vec<isize>, position: isize) -> Result<isize, String> {
-  let jump = instructions[position as usize];
-  if jump >= 3 {
-    instructions[position as usize] -= 1;
-  } else {
-    instructions[position as usize] += 1;
+  jump_with(instructions, position, &Strange)
+}
+
+fn jump_with<R: Rule>(
+  instructions: &mut [isize],
+  position: isize,
+  rule: &R,
+) -> Result<isize, String> {
+  if position < 0 || position as usize >= instructions.len() {
+    return Err("Out of bounds.".to_string());
   }
+  let jump = instructions[position as usize];
+  let offset = jump as i32;
+  instructions[position as usize] = rule
+    .update(offset)
+    .filter(|_| offset as isize == jump)
+    .ok_or_else(|| format!("Offset {} out of range.", jump))? as isize;
   if jump < -position || position + jump > (instructions.len() as isize) {
     return Err("Out of bounds.".to_string());
   }
-  return Ok(position + jump);
+  Ok(position + jump)
 }
 
+/// Count the jumps made by `jumper` until the position leaves the maze.
 pub fn run_instructions(
   instructions: &mut Vec<isize>,
   position: isize,
@@ -32,13 +290,15 @@ pub fn run_instructions(
     position = jumper(instructions, position)?;
     nsteps += 1;
   }
-  return Ok(nsteps);
+  Ok(nsteps)
 }
 
 #[cfg(test)]
 mod tests {
   use super::*;
 
+  const EXAMPLE: [i32; 5] = [0, 3, 0, 1, -3];
+
   #[test]
   fn jump_maze() {
     let mut instructions = vec![0, 3, 0, 1, -3];
@@ -64,4 +324,110 @@ mod tests {
     assert_eq!(instructions, vec![1, 3, 0, 1, -3]);
   }
 
+  #[test]
+  fn jump_from_outside() {
+    let mut instructions = vec![0, 3, 0, 1, -3];
+    let len = instructions.len() as isize;
+    assert!(jump_instruction(&mut instructions, -1).is_err());
+    assert!(jump_instruction(&mut instructions, len).is_err());
+    assert!(jump_fancy(&mut instructions, len).is_err());
+    assert_eq!(instructions, vec![0, 3, 0, 1, -3]);
+  }
+
+  #[test]
+  fn escape_maze() {
+    assert_eq!(escape(&EXAMPLE, Increment), Ok(5));
+  }
+
+  #[test]
+  fn strange_maze_offsets() {
+    let mut maze = Maze::new(EXAMPLE.to_vec(), Strange);
+    assert_eq!(maze.run(None), Ok(Exit::Back));
+    assert_eq!(maze.steps(), 10);
+    assert_eq!(maze.offsets(), &[2, 3, 2, 3, -1]);
+  }
+
+  #[test]
+  fn maze_step_once() {
+    let mut maze = Maze::new(EXAMPLE.to_vec(), Increment);
+    assert_eq!(maze.step(), Ok(None));
+    assert_eq!(maze.position(), 0);
+    assert_eq!(maze.offsets(), &[1, 3, 0, 1, -3]);
+  }
+
+  #[test]
+  fn exit_is_remembered() {
+    let mut maze = Maze::new(EXAMPLE.to_vec(), Increment);
+    assert_eq!(maze.run(None), Ok(Exit::Back));
+    assert_eq!(maze.run(None), Ok(Exit::Back));
+    assert_eq!(maze.step(), Ok(Some(Exit::Back)));
+    assert_eq!(maze.steps(), 5);
+    assert_eq!(maze.offsets(), &[2, 5, 0, 1, -2]);
+
+    let mut traced = Maze::new(vec![-1], Increment).with_trace();
+    assert_eq!(traced.run(Some(10)), Ok(Exit::Front));
+    assert_eq!(traced.run(Some(10)), Ok(Exit::Front));
+    assert_eq!(traced.exit(), Some(Exit::Front));
+    assert_eq!(traced.trace().unwrap().len(), 1);
+  }
+
+  #[test]
+  fn offset_overflow() {
+    let mut stuck = Maze::new(vec![i32::max_value()], Increment);
+    assert_eq!(
+      stuck.run(None),
+      Err(MazeError::Overflow {
+        position: 0,
+        offset: i32::max_value(),
+      })
+    );
+    assert_eq!(stuck.steps(), 0);
+    assert_eq!(stuck.exit(), None);
+
+    let mut maze = Maze::new(vec![0, i32::max_value()], Increment);
+    maze.step().unwrap();
+    maze.step().unwrap();
+    assert_eq!(
+      maze.step(),
+      Err(MazeError::Overflow {
+        position: 1,
+        offset: i32::max_value(),
+      })
+    );
+    assert_eq!(maze.offsets(), &[2, i32::max_value()]);
+    assert_eq!(Increment.update(i32::max_value()), None);
+  }
+
+  #[test]
+  fn fall_off_front() {
+    let mut maze = Maze::new(vec![1, -5, 0], Increment).with_trace();
+    assert_eq!(maze.run(None), Ok(Exit::Front));
+    assert_eq!(maze.steps(), 2);
+    assert_eq!(
+      maze.trace().unwrap()[1],
+      Jump {
+        from: 1,
+        offset: -5,
+        to: -4,
+      }
+    );
+  }
+
+  #[test]
+  fn step_limit() {
+    let mut maze = Maze::new(vec![0], |offset| offset);
+    assert_eq!(maze.run(Some(100)), Ok(Exit::StepLimit));
+    assert_eq!(maze.steps(), 100);
+  }
+
+  #[test]
+  fn trace_matches_fast() {
+    let mut traced = Maze::new(EXAMPLE.to_vec(), Strange).with_trace();
+    let mut fast = Maze::new(EXAMPLE.to_vec(), Strange);
+    assert_eq!(traced.run(None), fast.run(None));
+    assert_eq!(traced.steps(), fast.steps());
+    assert_eq!(traced.position(), fast.position());
+    assert_eq!(traced.offsets(), fast.offsets());
+    assert_eq!(traced.trace().unwrap().len() as u64, traced.steps());
+  }
 }
@@ -1,31 +1,200 @@
-use std::collections::HashMap;
+//! Day 6: Memory Reallocation
+//!
+//! Each reallocation picks a bank and hands its blocks out to
+//! other banks. How the bank is picked and where its blocks go
+//! is decided by a `Policy`; the puzzle uses `Largest`.
 
-fn reallocate_memory(memory_bank: &mut Vec<usize>) {
-  let largest = memory_bank
+use std::cell::RefCell;
+
+use super::super::cycle;
+
+/// A way of redistributing the blocks in one bank.
+pub trait Policy {
+  /// Perform a single reallocation.
+  fn reallocate(&self, banks: &mut [usize]);
+}
+
+/// The bank with the most blocks, lowest index on ties.
+fn largest(banks: &[usize]) -> Option<usize> {
+  banks
     .iter()
     .enumerate()
     .rev()
     .max_by_key(|x| x.1)
-    .unwrap()
-    .0;
-  let values = memory_bank[largest];
-  let vsize = memory_bank.len();
-  memory_bank[largest] = 0;
-  for i in 0..values {
-    let index = (largest + i + 1) % vsize;
-    memory_bank[index] += 1;
+    .map(|x| x.0)
+}
+
+/// The non-empty bank with the fewest blocks, lowest index on ties.
+fn smallest(banks: &[usize]) -> Option<usize> {
+  banks
+    .iter()
+    .enumerate()
+    .filter(|x| *x.1 > 0)
+    .min_by_key(|x| x.1)
+    .map(|x| x.0)
+}
+
+/// Empty a bank, handing out its blocks `chunk` at a
+/// time to each following bank in turn, wrapping around.
+fn round_robin(banks: &mut [usize], bank: usize, chunk: usize) {
+  let mut blocks = banks[bank];
+  banks[bank] = 0;
+  let mut index = bank;
+  while blocks > 0 {
+    index = (index + 1) % banks.len();
+    let given = blocks.min(chunk);
+    banks[index] += given;
+    blocks -= given;
+  }
+}
+
+/// The puzzle's policy: empty the largest bank,
+/// one block to each following bank in turn.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Largest;
+
+impl Policy for Largest {
+  fn reallocate(&self, banks: &mut [usize]) {
+    if let Some(bank) = largest(banks) {
+      round_robin(banks, bank, 1);
+    }
   }
 }
 
-pub fn reallocate_many(mut memory_bank: Vec<usize>) -> (u32, u32) {
-  let mut seen = HashMap::new();
-  let mut nsteps: u32 = 0;
-  while !seen.contains_key(&memory_bank) {
-    seen.insert(memory_bank.clone(), nsteps);
-    reallocate_memory(&mut memory_bank);
-    nsteps += 1;
+/// Empty the smallest non-empty bank,
+/// one block to each following bank in turn.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Smallest;
+
+impl Policy for Smallest {
+  fn reallocate(&self, banks: &mut [usize]) {
+    if let Some(bank) = smallest(banks) {
+      round_robin(banks, bank, 1);
+    }
+  }
+}
+
+/// Empty the largest bank, handing out a fixed number of
+/// blocks to each following bank in turn. The last bank
+/// to receive blocks may get fewer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Chunked(pub usize);
+
+impl Policy for Chunked {
+  fn reallocate(&self, banks: &mut [usize]) {
+    assert!(self.0 > 0, "chunks must contain at least one block");
+    if let Some(bank) = largest(banks) {
+      round_robin(banks, bank, self.0);
+    }
+  }
+}
+
+/// Empty the largest bank, splitting its blocks between the
+/// banks either side of it. The bank to the right gets the
+/// odd block, and the banks wrap around.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Neighbors;
+
+impl Policy for Neighbors {
+  fn reallocate(&self, banks: &mut [usize]) {
+    let bank = match largest(banks) {
+      Some(bank) => bank,
+      None => return,
+    };
+    let n = banks.len();
+    let blocks = banks[bank];
+    banks[bank] = 0;
+    banks[(bank + n - 1) % n] += blocks / 2;
+    banks[(bank + 1) % n] += blocks - blocks / 2;
+  }
+}
+
+/// A set of memory banks, reallocated using a policy.
+pub struct Memory<P> {
+  banks: Vec<usize>,
+  policy: P,
+  history: Option<Vec<Vec<usize>>>,
+}
+
+impl<P> Memory<P>
+where
+  P: Policy,
+{
+  pub fn new(banks: Vec<usize>, policy: P) -> Self {
+    Memory {
+      banks,
+      policy,
+      history: None,
+    }
+  }
+
+  /// Record every state the banks pass through,
+  /// starting with the current one.
+  pub fn with_history(mut self) -> Self {
+    self.history = Some(vec![self.banks.clone()]);
+    self
+  }
+
+  pub fn banks(&self) -> &[usize] {
+    &self.banks
+  }
+
+  /// The recorded states, if history is being recorded.
+  pub fn history(&self) -> Option<&[Vec<usize>]> {
+    self.history.as_deref()
+  }
+
+  /// Perform a single reallocation.
+  pub fn step(&mut self) {
+    self.policy.reallocate(&mut self.banks);
+    if let Some(ref mut history) = self.history {
+      history.push(self.banks.clone());
+    }
+  }
+
+  /// Reallocate until a state repeats, returning `(mu, lambda)`:
+  /// the step the repeated state first appeared at, and the
+  /// length of the cycle. The banks are left in the repeated state.
+  ///
+  /// Without history, the cycle is found with `cycle::brent`. With
+  /// history, it is found with `cycle::hashed`, which reallocates
+  /// each state once, and the states it visits are recorded.
+  pub fn run(&mut self) -> (usize, usize) {
+    let policy = &self.policy;
+    let reallocate = |banks: &Vec<usize>| {
+      let mut banks = banks.clone();
+      policy.reallocate(&mut banks);
+      banks
+    };
+    match self.history {
+      None => {
+        let (mu, lambda) = cycle::brent(&self.banks, reallocate);
+        self.banks = cycle::nth_state(&self.banks, reallocate, mu);
+        (mu, lambda)
+      }
+      Some(ref mut history) => {
+        let visited = RefCell::new(Vec::new());
+        let (mu, lambda) = cycle::hashed(&self.banks, |banks| {
+          let next = reallocate(banks);
+          visited.borrow_mut().push(next.clone());
+          next
+        });
+        // `hashed` looks one state past the repeat.
+        let mut visited = visited.into_inner();
+        visited.truncate(mu + lambda);
+        history.extend(visited);
+        self.banks = history[history.len() - 1].clone();
+        (mu, lambda)
+      }
+    }
   }
-  (nsteps, nsteps - seen.get(&memory_bank).unwrap())
+}
+
+/// Reallocate until a state repeats, returning the number
+/// of steps until the first repeat and the cycle length.
+pub fn reallocate_many(memory_bank: Vec<usize>) -> (u32, u32) {
+  let (mu, lambda) = Memory::new(memory_bank, Largest).run();
+  ((mu + lambda) as u32, lambda as u32)
 }
 
 #[cfg(test)]
@@ -35,7 +204,7 @@ mod tests {
   #[test]
   fn memory_move() {
     let mut memory = vec![0, 2, 7, 0];
-    reallocate_memory(&mut memory);
+    Largest.reallocate(&mut memory);
     assert_eq!(memory, vec![2, 4, 1, 2]);
   }
 
@@ -45,4 +214,46 @@ mod tests {
     assert_eq!(reallocate_many(memory), (5, 4));
   }
 
+  #[test]
+  fn memory_history() {
+    let mut memory = Memory::new(vec![0, 2, 7, 0], Largest).with_history();
+    assert_eq!(memory.run(), (1, 4));
+    assert_eq!(memory.banks(), &[2, 4, 1, 2]);
+
+    let history = memory.history().unwrap();
+    assert_eq!(history.len(), 6);
+    assert_eq!(history[3], vec![0, 2, 3, 4]);
+    assert_eq!(history[1], history[5]);
+  }
+
+  #[test]
+  fn memory_without_history() {
+    let mut memory = Memory::new(vec![0, 2, 7, 0], Largest);
+    assert_eq!(memory.run(), (1, 4));
+    assert_eq!(memory.banks(), &[2, 4, 1, 2]);
+    assert!(memory.history().is_none());
+  }
+
+  #[test]
+  fn other_policies() {
+    let mut memory = vec![3, 1, 6, 2];
+    Smallest.reallocate(&mut memory);
+    assert_eq!(memory, vec![3, 0, 7, 2]);
+
+    let mut memory = vec![3, 1, 6, 2];
+    Chunked(4).reallocate(&mut memory);
+    assert_eq!(memory, vec![5, 1, 0, 6]);
+
+    let mut memory = vec![3, 1, 6, 2];
+    Neighbors.reallocate(&mut memory);
+    assert_eq!(memory, vec![3, 4, 0, 5]);
+  }
+
+  #[test]
+  fn empty_banks() {
+    let mut memory = vec![0, 0, 0];
+    Smallest.reallocate(&mut memory);
+    assert_eq!(memory, vec![0, 0, 0]);
+    assert_eq!(Memory::new(memory, Smallest).run(), (0, 1));
+  }
 }
@@ -1,39 +1,90 @@
+//! Day 8: I Heard You Like Registers
+//!
+//! An interpreter for the register language. Each instruction
+//! has the form `register command expression if condition`.
+//! On top of the puzzle's `inc` and `dec`, the language supports
+//! `mul` and `set` commands, arithmetic expressions with
+//! `+ - * / %` and parentheses, and conditions combined with
+//! `and`, `or` and `not`.
+
 use std::collections::HashMap;
-use std::io;
+use std::fmt;
 
 use super::super::vm;
 
 pub type Registers = HashMap<String, i32>;
 
-fn parser_error(text: String) -> io::Error {
-  io::Error::new(io::ErrorKind::InvalidInput, text)
+#[derive(Debug, Fail, Clone, PartialEq, Eq)]
+pub enum ProgramError {
+  #[fail(display = "unexpected character '{}' at {}", found, position)] UnexpectedCharacter {
+    position: usize,
+    found: char,
+  },
+
+  #[fail(display = "invalid number \"{}\" at {}", number, position)] InvalidNumber {
+    position: usize,
+    number: String,
+  },
+
+  #[fail(display = "unexpected {} at {}, expected {}", found, position, expected)]
+  UnexpectedToken {
+    position: usize,
+    found: String,
+    expected: &'static str,
+  },
+
+  #[fail(display = "unexpected end of instruction, expected {}", expected)] UnexpectedEnd {
+    expected: &'static str,
+  },
+
+  #[fail(display = "unknown command \"{}\"", command)] UnknownCommand {
+    command: String,
+  },
+
+  #[fail(display = "unknown comparison \"{}\"", operator)] UnknownOperator {
+    operator: String,
+  },
+
+  #[fail(display = "division by zero")] DivisionByZero,
+
+  #[fail(display = "arithmetic overflow")] Overflow,
 }
 
+type Result<T> = ::std::result::Result<T, ProgramError>;
+
 #[derive(Debug, PartialEq, Eq, Clone)]
 enum Command {
   Increment,
   Decrement,
+  Multiply,
+  Assign,
 }
 
 impl Command {
-  fn execute(&self, target: &mut i32, value: i32) {
+  fn execute(&self, target: i32, value: i32) -> Result<i32> {
     match *self {
-      Command::Increment => *target += value,
-      Command::Decrement => *target -= value,
-    }
+      Command::Increment => target.checked_add(value),
+      Command::Decrement => target.checked_sub(value),
+      Command::Multiply => target.checked_mul(value),
+      Command::Assign => Some(value),
+    }.ok_or(ProgramError::Overflow)
   }
 
-  fn parse(text: &str) -> Result<Command, io::Error> {
+  fn parse(text: &str) -> Result<Command> {
     match text {
       "inc" => Ok(Command::Increment),
       "dec" => Ok(Command::Decrement),
-      _ => Err(parser_error(format!("Can't parse {}", text))),
+      "mul" => Ok(Command::Multiply),
+      "set" => Ok(Command::Assign),
+      _ => Err(ProgramError::UnknownCommand {
+        command: text.to_string(),
+      }),
     }
   }
 }
 
-#[derive(Debug, PartialEq, Eq, Clone)]
-enum Operator {
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Operator {
   GreaterThan,
   LessThan,
   GreaterEqual,
@@ -54,7 +105,7 @@ impl Operator {
     }
   }
 
-  fn parse(text: &str) -> Result<Operator, io::Error> {
+  fn parse(text: &str) -> Result<Operator> {
     match text.trim() {
       ">" => Ok(Operator::GreaterThan),
       "<" => Ok(Operator::LessThan),
@@ -63,7 +114,369 @@ impl Operator {
       "==" => Ok(Operator::Equal),
       "!=" => Ok(Operator::NotEqual),
       "=!" => Ok(Operator::NotEqual),
-      _ => Err(parser_error(format!("Can't parse {}", text))),
+      _ => Err(ProgramError::UnknownOperator {
+        operator: text.to_string(),
+      }),
+    }
+  }
+}
+
+/// Binary arithmetic operations.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Arithmetic {
+  Add,
+  Subtract,
+  Multiply,
+  Divide,
+  Remainder,
+}
+
+impl Arithmetic {
+  fn execute(&self, left: i32, right: i32) -> Result<i32> {
+    match *self {
+      Arithmetic::Divide | Arithmetic::Remainder if right == 0 => {
+        return Err(ProgramError::DivisionByZero);
+      }
+      _ => {}
+    };
+    match *self {
+      Arithmetic::Add => left.checked_add(right),
+      Arithmetic::Subtract => left.checked_sub(right),
+      Arithmetic::Multiply => left.checked_mul(right),
+      Arithmetic::Divide => left.checked_div(right),
+      Arithmetic::Remainder => left.checked_rem(right),
+    }.ok_or(ProgramError::Overflow)
+  }
+}
+
+/// An arithmetic expression over registers and literal values.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Expression<'a> {
+  Argument(vm::Argument<'a, i32>),
+  Negate(Box<Expression<'a>>),
+  Binary(Box<Expression<'a>>, Arithmetic, Box<Expression<'a>>),
+}
+
+impl<'a> Expression<'a> {
+  pub fn evaluate(&self, registers: &vm::Registers<i32>) -> Result<i32> {
+    match *self {
+      Expression::Argument(ref argument) => Ok(registers.get(argument)),
+      Expression::Negate(ref e) => e.evaluate(registers)?
+        .checked_neg()
+        .ok_or(ProgramError::Overflow),
+      Expression::Binary(ref left, op, ref right) => {
+        op.execute(left.evaluate(registers)?, right.evaluate(registers)?)
+      }
+    }
+  }
+}
+
+/// A condition guarding an instruction.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Condition<'a> {
+  Compare(Expression<'a>, Operator, Expression<'a>),
+  And(Box<Condition<'a>>, Box<Condition<'a>>),
+  Or(Box<Condition<'a>>, Box<Condition<'a>>),
+  Not(Box<Condition<'a>>),
+}
+
+impl<'a> Condition<'a> {
+  /// Evaluate the condition. `and` and `or` short-circuit,
+  /// so errors on the right hand side may not be reported.
+  pub fn evaluate(&self, registers: &vm::Registers<i32>) -> Result<bool> {
+    match *self {
+      Condition::Compare(ref left, op, ref right) => {
+        Ok(op.execute(left.evaluate(registers)?, right.evaluate(registers)?))
+      }
+      Condition::And(ref left, ref right) => {
+        Ok(left.evaluate(registers)? && right.evaluate(registers)?)
+      }
+      Condition::Or(ref left, ref right) => {
+        Ok(left.evaluate(registers)? || right.evaluate(registers)?)
+      }
+      Condition::Not(ref c) => Ok(!c.evaluate(registers)?),
+    }
+  }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+enum Token<'a> {
+  Number(i32),
+  Name(&'a str),
+  Compare(Operator),
+  Arithmetic(Arithmetic),
+  Open,
+  Close,
+}
+
+impl<'a> fmt::Display for Token<'a> {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match *self {
+      Token::Number(n) => write!(f, "number {}", n),
+      Token::Name(name) => write!(f, "\"{}\"", name),
+      Token::Compare(op) => write!(f, "comparison {:?}", op),
+      Token::Arithmetic(op) => write!(f, "operator {:?}", op),
+      Token::Open => write!(f, "'('"),
+      Token::Close => write!(f, "')'"),
+    }
+  }
+}
+
+/// Split an instruction into tokens, along with
+/// the position where each token starts.
+fn tokenize<'a>(text: &'a str) -> Result<Vec<(usize, Token<'a>)>> {
+  let mut tokens = Vec::new();
+  let mut chars = text.char_indices().peekable();
+
+  while let Some((i, c)) = chars.next() {
+    let token = match c {
+      _ if c.is_whitespace() => continue,
+      '0'..='9' => {
+        let mut end = i + 1;
+        while let Some(&(j, '0'..='9')) = chars.peek() {
+          end = j + 1;
+          chars.next();
+        }
+        let number = &text[i..end];
+        Token::Number(number
+          .parse()
+          .map_err(|_| ProgramError::InvalidNumber {
+            position: i,
+            number: number.to_string(),
+          })?)
+      }
+      _ if c.is_alphabetic() || c == '_' => {
+        let mut end = i + c.len_utf8();
+        while let Some(&(j, d)) = chars.peek() {
+          if !(d.is_alphanumeric() || d == '_') {
+            break;
+          }
+          end = j + d.len_utf8();
+          chars.next();
+        }
+        Token::Name(&text[i..end])
+      }
+      '<' | '>' | '=' | '!' => {
+        let mut end = i + 1;
+        if let Some(&(j, '=')) = chars.peek() {
+          end = j + 1;
+          chars.next();
+        } else if let Some(&(j, '!')) = chars.peek() {
+          if c == '=' {
+            end = j + 1;
+            chars.next();
+          }
+        }
+        Token::Compare(Operator::parse(&text[i..end]).map_err(|_| {
+          ProgramError::UnexpectedCharacter {
+            position: i,
+            found: c,
+          }
+        })?)
+      }
+      '+' => Token::Arithmetic(Arithmetic::Add),
+      '-' => Token::Arithmetic(Arithmetic::Subtract),
+      '*' => Token::Arithmetic(Arithmetic::Multiply),
+      '/' => Token::Arithmetic(Arithmetic::Divide),
+      '%' => Token::Arithmetic(Arithmetic::Remainder),
+      '(' => Token::Open,
+      ')' => Token::Close,
+      _ => {
+        return Err(ProgramError::UnexpectedCharacter {
+          position: i,
+          found: c,
+        })
+      }
+    };
+    tokens.push((i, token));
+  }
+  Ok(tokens)
+}
+
+/// A recursive descent parser over the tokens of one instruction.
+/// The parser can be rewound, which is used to tell a parenthesized
+/// condition from a parenthesized expression.
+struct Parser<'a> {
+  tokens: Vec<(usize, Token<'a>)>,
+  index: usize,
+}
+
+impl<'a> Parser<'a> {
+  fn peek(&self) -> Option<Token<'a>> {
+    self.tokens.get(self.index).map(|&(_, t)| t)
+  }
+
+  fn next(&mut self, expected: &'static str) -> Result<(usize, Token<'a>)> {
+    let token = *self
+      .tokens
+      .get(self.index)
+      .ok_or(ProgramError::UnexpectedEnd { expected })?;
+    self.index += 1;
+    Ok(token)
+  }
+
+  fn unexpected<T>(&self, expected: &'static str) -> Result<T> {
+    match self.tokens.get(self.index) {
+      Some(&(position, token)) => Err(ProgramError::UnexpectedToken {
+        position,
+        found: token.to_string(),
+        expected,
+      }),
+      None => Err(ProgramError::UnexpectedEnd { expected }),
+    }
+  }
+
+  fn name(&mut self, expected: &'static str) -> Result<&'a str> {
+    match self.peek() {
+      Some(Token::Name(name)) => {
+        self.index += 1;
+        Ok(name)
+      }
+      _ => self.unexpected(expected),
+    }
+  }
+
+  fn keyword(&mut self, keyword: &'static str) -> bool {
+    if self.peek() == Some(Token::Name(keyword)) {
+      self.index += 1;
+      true
+    } else {
+      false
+    }
+  }
+
+  fn close(&mut self) -> Result<()> {
+    match self.peek() {
+      Some(Token::Close) => {
+        self.index += 1;
+        Ok(())
+      }
+      _ => self.unexpected("')'"),
+    }
+  }
+
+  /// `instruction := name command expression "if" condition`
+  fn instruction(&mut self) -> Result<Instruction<'a>> {
+    let destination = self.name("a register")?;
+    let command = Command::parse(self.name("a command")?)?;
+    let value = self.expression()?;
+    if !self.keyword("if") {
+      return self.unexpected("\"if\"");
+    }
+    let condition = self.condition()?;
+    if self.peek().is_some() {
+      return self.unexpected("end of instruction");
+    }
+    Ok(Instruction {
+      destination: vm::Argument::Register(destination),
+      command,
+      value,
+      condition,
+    })
+  }
+
+  /// `condition := conjunction ("or" conjunction)*`
+  fn condition(&mut self) -> Result<Condition<'a>> {
+    let mut condition = self.conjunction()?;
+    while self.keyword("or") {
+      condition = Condition::Or(Box::new(condition), Box::new(self.conjunction()?));
+    }
+    Ok(condition)
+  }
+
+  /// `conjunction := negation ("and" negation)*`
+  fn conjunction(&mut self) -> Result<Condition<'a>> {
+    let mut condition = self.negation()?;
+    while self.keyword("and") {
+      condition = Condition::And(Box::new(condition), Box::new(self.negation()?));
+    }
+    Ok(condition)
+  }
+
+  /// `negation := comparison | "not" negation | "(" condition ")"`
+  ///
+  /// Comparisons are tried first, so that registers may be named
+  /// `not`, and so that `(a + 1) > b` isn't taken as a condition.
+  fn negation(&mut self) -> Result<Condition<'a>> {
+    let start = self.index;
+    let error = match self.comparison() {
+      Ok(c) => return Ok(c),
+      Err(e) => e,
+    };
+
+    self.index = start;
+    if self.keyword("not") {
+      return Ok(Condition::Not(Box::new(self.negation()?)));
+    }
+    if self.peek() == Some(Token::Open) {
+      self.index += 1;
+      let condition = self.condition()?;
+      self.close()?;
+      return Ok(condition);
+    }
+    Err(error)
+  }
+
+  /// `comparison := expression operator expression`
+  fn comparison(&mut self) -> Result<Condition<'a>> {
+    let left = self.expression()?;
+    let op = match self.peek() {
+      Some(Token::Compare(op)) => op,
+      _ => return self.unexpected("a comparison"),
+    };
+    self.index += 1;
+    let right = self.expression()?;
+    Ok(Condition::Compare(left, op, right))
+  }
+
+  /// `expression := term (("+" | "-") term)*`
+  fn expression(&mut self) -> Result<Expression<'a>> {
+    let mut expression = self.term()?;
+    while let Some(Token::Arithmetic(op)) = self.peek() {
+      if op != Arithmetic::Add && op != Arithmetic::Subtract {
+        break;
+      }
+      self.index += 1;
+      expression = Expression::Binary(Box::new(expression), op, Box::new(self.term()?));
+    }
+    Ok(expression)
+  }
+
+  /// `term := factor (("*" | "/" | "%") factor)*`
+  fn term(&mut self) -> Result<Expression<'a>> {
+    let mut term = self.factor()?;
+    while let Some(Token::Arithmetic(op)) = self.peek() {
+      if op == Arithmetic::Add || op == Arithmetic::Subtract {
+        break;
+      }
+      self.index += 1;
+      term = Expression::Binary(Box::new(term), op, Box::new(self.factor()?));
+    }
+    Ok(term)
+  }
+
+  /// `factor := number | register | "-" factor | "(" expression ")"`
+  fn factor(&mut self) -> Result<Expression<'a>> {
+    match self.next("an expression")? {
+      (_, Token::Number(n)) => Ok(Expression::Argument(vm::Argument::Value(n))),
+      (_, Token::Name(name)) => Ok(Expression::Argument(vm::Argument::Register(name))),
+      (_, Token::Arithmetic(Arithmetic::Subtract)) => {
+        // Fold negative literals into a single value.
+        if let Some(Token::Number(n)) = self.peek() {
+          self.index += 1;
+          return Ok(Expression::Argument(vm::Argument::Value(-n)));
+        }
+        Ok(Expression::Negate(Box::new(self.factor()?)))
+      }
+      (_, Token::Open) => {
+        let expression = self.expression()?;
+        self.close()?;
+        Ok(expression)
+      }
+      _ => {
+        self.index -= 1;
+        self.unexpected("an expression")
+      }
     }
   }
 }
@@ -72,47 +485,41 @@ impl Operator {
 pub struct Instruction<'a> {
   destination: vm::Argument<'a, i32>,
   command: Command,
-  value: vm::Argument<'a, i32>,
-  left: vm::Argument<'a, i32>,
-  operator: Operator,
-  right: vm::Argument<'a, i32>,
+  value: Expression<'a>,
+  condition: Condition<'a>,
 }
 
 impl<'a> Instruction<'a> {
   /// Check the condition for this instruction.
-  fn condition(&self, registers: &vm::Registers<i32>) -> bool {
-    let left = registers.get(&self.left);
-    let right = registers.get(&self.right);
-    self.operator.execute(left, right)
+  fn condition(&self, registers: &vm::Registers<i32>) -> Result<bool> {
+    self.condition.evaluate(registers)
   }
 
-  pub fn execute(&self, registers: &mut vm::Registers<i32>) {
-    if self.condition(registers) {
-      let right = registers.get(&self.value);
-      let left = registers.get_mut(&self.destination).unwrap();
-      self.command.execute(left, right);
-    }
+  /// Execute the instruction, returning whether
+  /// the condition held and the command ran.
+  /// Each instruction is one step of the register history.
+  pub fn execute(&self, registers: &mut vm::Registers<i32>) -> Result<bool> {
+    let result = self.run(registers);
+    registers.tick();
+    result
   }
 
-  pub fn parse(text: &str) -> Result<Instruction, io::Error> {
-    let mut parts = text.split_whitespace();
-    let destination = parts.next().unwrap().into();
-    let command = Command::parse(parts.next().unwrap())?;
-    let value = parts.next().unwrap().into();
-
-    let _ifstatement = parts.next().unwrap();
-    let left = parts.next().unwrap().into();
-    let op = Operator::parse(parts.next().unwrap())?;
-    let right = parts.next().unwrap().into();
+  fn run(&self, registers: &mut vm::Registers<i32>) -> Result<bool> {
+    if !self.condition(registers)? {
+      return Ok(false);
+    }
+    let value = self.value.evaluate(registers)?;
+    let target = registers.get(&self.destination);
+    registers.set(&self.destination, self.command.execute(target, value)?);
+    Ok(true)
+  }
 
-    Ok(Instruction {
-      destination: destination,
-      command: command,
-      value: value,
-      left: left,
-      operator: op,
-      right: right,
-    })
+  pub fn parse<'b>(text: &'b str) -> Result<Instruction<'b>> {
+    let mut parser = Parser {
+      tokens: tokenize(text)?,
+      index: 0,
+    };
+    parser.instruction()
   }
 }
 
@@ -149,7 +556,7 @@ mod test {
     let mut registers = vm::Registers::new(0);
     let text = "a inc 10 if b < 5";
     let instruction = Instruction::parse(text).unwrap();
-    instruction.execute(&mut registers);
+    instruction.execute(&mut registers).unwrap();
     assert_eq!(registers.hmap().get("a").unwrap(), &10);
   }
 
@@ -160,12 +567,14 @@ mod test {
       let instruction = Instruction {
         destination: vm::Argument::Register("a"),
         command: Command::Increment,
-        value: vm::Argument::Value(10),
-        left: vm::Argument::Register("b"),
-        operator: Operator::LessThan,
-        right: vm::Argument::Value(5),
+        value: Expression::Argument(vm::Argument::Value(10)),
+        condition: Condition::Compare(
+          Expression::Argument(vm::Argument::Register("b")),
+          Operator::LessThan,
+          Expression::Argument(vm::Argument::Value(5)),
+        ),
       };
-      instruction.execute(&mut registers);
+      instruction.execute(&mut registers).unwrap();
     }
     assert_eq!(registers.hmap().get("a").unwrap(), &10);
   }
@@ -180,8 +589,139 @@ c inc -20 if c == 10";
     for statement in program.as_bytes().lines() {
       let line = statement.unwrap();
       let instruction = Instruction::parse(&line).unwrap();
-      instruction.execute(&mut registers);
+      instruction.execute(&mut registers).unwrap();
     }
     assert_eq!(registers.hmap().values().max(), Some(&1));
   }
+
+  #[test]
+  fn program_history() {
+    let program = "b inc 5 if a > 1
+a inc 1 if b < 5
+c dec -10 if a >= 1
+c inc -20 if c == 10";
+    let mut registers = vm::Registers::with_history(0);
+    for line in program.lines() {
+      Instruction::parse(line)
+        .unwrap()
+        .execute(&mut registers)
+        .unwrap();
+    }
+    assert_eq!(registers.max_ever(), Some(10));
+    assert_eq!(registers.first_above(5).map(|w| w.step), Some(2));
+    assert_eq!(registers.report(), vec![("a", 1), ("c", -10)]);
+    assert_eq!(registers.history().unwrap().len(), 3);
+  }
+
+  fn run(program: &str) -> vm::Registers<i32> {
+    let mut registers = vm::Registers::new(0);
+    for line in program.lines() {
+      Instruction::parse(line)
+        .unwrap()
+        .execute(&mut registers)
+        .unwrap();
+    }
+    registers
+  }
+
+  fn register(registers: &vm::Registers<i32>, name: &str) -> i32 {
+    registers.get(&vm::Argument::Register(name))
+  }
+
+  #[test]
+  fn execute_expressions() {
+    let registers = run(
+      "a set 2 + 3 * 4 if 1 == 1
+b set (a - 4) * -2 if a > 0
+c set a / 3 + a % 3 if b < 0
+a mul 2 if not a < 14
+d set -(b + 1) if a == 28",
+    );
+    assert_eq!(register(&registers, "a"), 28);
+    assert_eq!(register(&registers, "b"), -20);
+    assert_eq!(register(&registers, "c"), 6);
+    assert_eq!(register(&registers, "d"), 19);
+  }
+
+  #[test]
+  fn execute_compound_conditions() {
+    let registers = run(
+      "a inc 1 if x == 0 and y == 0
+b inc 1 if x != 0 or y == 0
+c inc 1 if not (x == 0 or y == 0)
+d inc 1 if (x + 1) > 0 and not x > 0 or a > 5
+e inc 1 if x > 0 or x == 0 and a == 0",
+    );
+    assert_eq!(register(&registers, "a"), 1);
+    assert_eq!(register(&registers, "b"), 1);
+    assert_eq!(register(&registers, "c"), 0);
+    assert_eq!(register(&registers, "d"), 1);
+    // `and` binds tighter than `or`.
+    assert_eq!(register(&registers, "e"), 0);
+  }
+
+  #[test]
+  fn keyword_registers() {
+    let registers = run(
+      "not inc 3 if and == 0
+or inc not if not > 2",
+    );
+    assert_eq!(register(&registers, "not"), 3);
+    assert_eq!(register(&registers, "or"), 3);
+  }
+
+  #[test]
+  fn parse_errors() {
+    assert_eq!(
+      Instruction::parse("a inc 1 if b $ 2"),
+      Err(ProgramError::UnexpectedCharacter {
+        position: 13,
+        found: '$',
+      })
+    );
+    assert_eq!(
+      Instruction::parse("a foo 1 if b > 2"),
+      Err(ProgramError::UnknownCommand {
+        command: "foo".to_string(),
+      })
+    );
+    assert_eq!(
+      Instruction::parse("a inc 1 when b > 2"),
+      Err(ProgramError::UnexpectedToken {
+        position: 8,
+        found: "\"when\"".to_string(),
+        expected: "\"if\"",
+      })
+    );
+    assert_eq!(
+      Instruction::parse("a inc (1 if b > 2"),
+      Err(ProgramError::UnexpectedToken {
+        position: 9,
+        found: "\"if\"".to_string(),
+        expected: "')'",
+      })
+    );
+    assert_eq!(
+      Instruction::parse("a inc 1 if b >"),
+      Err(ProgramError::UnexpectedEnd {
+        expected: "an expression",
+      })
+    );
+    assert!(Instruction::parse("a inc 99999999999 if b > 2").is_err());
+  }
+
+  #[test]
+  fn runtime_errors() {
+    let mut registers = vm::Registers::new(0);
+    let instruction = Instruction::parse("a inc 1 / b if 1 > 0").unwrap();
+    assert_eq!(
+      instruction.execute(&mut registers),
+      Err(ProgramError::DivisionByZero)
+    );
+    let instruction = Instruction::parse("a inc 2147483647 * 2 if 1 > 0").unwrap();
+    assert_eq!(
+      instruction.execute(&mut registers),
+      Err(ProgramError::Overflow)
+    );
+  }
 }
@@ -1,35 +1,379 @@
-pub fn procress_stream(text: &str) -> (u32, u32) {
-  let mut score = 0;
-  let mut garbage = false;
-  let mut ngarbage = 0;
-  let mut group = 0;
-  let mut chars = text.chars();
+//! Day 9: Stream Processing
+//!
+//! Streams are made of groups, in `{}`, which contain a comma
+//! separated list of groups and garbage. Garbage is in `<>`, and
+//! `!` cancels the character after it inside garbage.
+
+use std::io;
+use std::io::Read;
+use std::iter::Peekable;
+use std::str::CharIndices;
+
+/// The size of the chunks read by `StreamProcessor::process`.
+const CHUNK_SIZE: usize = 8192;
+
+/// The deepest nesting of groups `parse_stream` will build a tree
+/// for. The parser and the tree are both recursive, so deeper
+/// streams would overflow the stack; `StreamProcessor` has no limit.
+pub const MAX_DEPTH: usize = 1000;
+
+#[derive(Debug, Fail, Clone, PartialEq, Eq)]
+pub enum StreamError {
+  #[fail(display = "unexpected '{}' at {}, expected {}", found, position, expected)]
+  UnexpectedCharacter {
+    position: usize,
+    found: char,
+    expected: &'static str,
+  },
+
+  #[fail(display = "unexpected end of stream")] UnexpectedEnd,
+
+  #[fail(display = "group starting at {} is never closed", start)] UnterminatedGroup {
+    start: usize,
+  },
+
+  #[fail(display = "garbage starting at {} is never closed", start)] UnterminatedGarbage {
+    start: usize,
+  },
+
+  #[fail(display = "unbalanced '}}' at {}", position)] UnbalancedBrace {
+    position: usize,
+  },
+
+  #[fail(display = "group at {} is nested more than {} deep", position, max)] TooDeep {
+    position: usize,
+    max: usize,
+  },
+}
+
+type Result<T> = ::std::result::Result<T, StreamError>;
+
+/// A range of bytes in the stream, from `start` up to
+/// but not including `end`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Span {
+  pub start: usize,
+  pub end: usize,
+}
+
+/// A group, and everything inside it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Group {
+  span: Span,
+  children: Vec<Node>,
+}
+
+impl Group {
+  pub fn span(&self) -> Span {
+    self.span
+  }
+
+  pub fn children(&self) -> &[Node] {
+    &self.children
+  }
+}
+
+/// A segment of garbage, including its angle brackets.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Garbage {
+  span: Span,
+  cancelled: Vec<usize>,
+  count: usize,
+}
+
+impl Garbage {
+  pub fn span(&self) -> Span {
+    self.span
+  }
+
+  /// The positions of the characters cancelled by a `!`.
+  pub fn cancelled(&self) -> &[usize] {
+    &self.cancelled
+  }
+
+  /// The number of characters of garbage, not counting
+  /// the brackets, `!`s, or cancelled characters.
+  pub fn count(&self) -> usize {
+    self.count
+  }
+}
+
+/// A single item in the stream.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Node {
+  Group(Group),
+  Garbage(Garbage),
+}
+
+impl Node {
+  pub fn span(&self) -> Span {
+    match *self {
+      Node::Group(ref g) => g.span(),
+      Node::Garbage(ref g) => g.span(),
+    }
+  }
 
-  while let Some(ch) = chars.next() {
-    match ch {
-      '!' => {
-        chars.next();
+  /// The total score of the groups in this node,
+  /// where each group scores its depth.
+  pub fn score(&self) -> u32 {
+    self.score_at(1)
+  }
+
+  fn score_at(&self, depth: u32) -> u32 {
+    match *self {
+      Node::Group(ref g) => {
+        depth
+          + g.children
+            .iter()
+            .map(|c| c.score_at(depth + 1))
+            .sum::<u32>()
       }
-      '>' => {
-        garbage = false;
+      Node::Garbage(_) => 0,
+    }
+  }
+
+  /// The number of groups in this node.
+  pub fn groups(&self) -> usize {
+    match *self {
+      Node::Group(ref g) => 1 + g.children.iter().map(|c| c.groups()).sum::<usize>(),
+      Node::Garbage(_) => 0,
+    }
+  }
+
+  /// The number of characters of garbage in this node.
+  pub fn garbage(&self) -> usize {
+    match *self {
+      Node::Group(ref g) => g.children.iter().map(|c| c.garbage()).sum(),
+      Node::Garbage(ref g) => g.count(),
+    }
+  }
+}
+
+struct Parser<'a> {
+  chars: Peekable<CharIndices<'a>>,
+  depth: usize,
+}
+
+impl<'a> Parser<'a> {
+  /// Parse a group or garbage, starting with `first`.
+  fn node(&mut self, first: (usize, char)) -> Result<Node> {
+    match first {
+      (i, '{') => self.group(i).map(Node::Group),
+      (i, '<') => self.garbage(i).map(Node::Garbage),
+      (i, '}') => Err(StreamError::UnbalancedBrace { position: i }),
+      (i, c) => Err(StreamError::UnexpectedCharacter {
+        position: i,
+        found: c,
+        expected: "'{' or '<'",
+      }),
+    }
+  }
+
+  /// Parse the rest of a group which opened at `start`.
+  fn group(&mut self, start: usize) -> Result<Group> {
+    if self.depth == MAX_DEPTH {
+      return Err(StreamError::TooDeep {
+        position: start,
+        max: MAX_DEPTH,
+      });
+    }
+    self.depth += 1;
+    let group = self.group_contents(start);
+    self.depth -= 1;
+    group
+  }
+
+  fn group_contents(&mut self, start: usize) -> Result<Group> {
+    let mut children = Vec::new();
+
+    if let Some(&(i, '}')) = self.chars.peek() {
+      self.chars.next();
+      return Ok(Group {
+        span: Span { start, end: i + 1 },
+        children,
+      });
+    }
+
+    loop {
+      let first = self
+        .chars
+        .next()
+        .ok_or(StreamError::UnterminatedGroup { start })?;
+      children.push(self.node(first)?);
+      match self.chars.next() {
+        Some((_, ',')) => {}
+        Some((i, '}')) => {
+          return Ok(Group {
+            span: Span { start, end: i + 1 },
+            children,
+          });
+        }
+        Some((i, c)) => {
+          return Err(StreamError::UnexpectedCharacter {
+            position: i,
+            found: c,
+            expected: "',' or '}'",
+          });
+        }
+        None => return Err(StreamError::UnterminatedGroup { start }),
       }
-      _ if garbage => {
-        ngarbage += 1;
+    }
+  }
+
+  /// Parse the rest of some garbage which opened at `start`.
+  fn garbage(&mut self, start: usize) -> Result<Garbage> {
+    let mut cancelled = Vec::new();
+    let mut count = 0;
+    loop {
+      match self.chars.next() {
+        Some((_, '!')) => match self.chars.next() {
+          Some((i, _)) => cancelled.push(i),
+          None => break,
+        },
+        Some((i, '>')) => {
+          return Ok(Garbage {
+            span: Span { start, end: i + 1 },
+            cancelled,
+            count,
+          });
+        }
+        Some(_) => count += 1,
+        None => break,
       }
-      '<' => {
-        garbage = true;
+    }
+    Err(StreamError::UnterminatedGarbage { start })
+  }
+}
+
+/// Parse a stream into a tree of groups and garbage.
+/// The stream must hold exactly one group or garbage
+/// segment, optionally followed by whitespace.
+pub fn parse_stream(text: &str) -> Result<Node> {
+  let mut parser = Parser {
+    chars: text.char_indices().peekable(),
+    depth: 0,
+  };
+  let first = parser.chars.next().ok_or(StreamError::UnexpectedEnd)?;
+  let node = parser.node(first)?;
+  match parser.chars.find(|&(_, c)| !c.is_whitespace()) {
+    Some((i, '}')) => Err(StreamError::UnbalancedBrace { position: i }),
+    Some((i, c)) => Err(StreamError::UnexpectedCharacter {
+      position: i,
+      found: c,
+      expected: "end of stream",
+    }),
+    None => Ok(node),
+  }
+}
+
+/// A push-based stream processor, which keeps running totals as
+/// chunks of a stream arrive. All of its state, including a `!`
+/// waiting to cancel the next character, is carried across chunk
+/// boundaries, so the stream never needs to be held in memory.
+///
+/// The processor doesn't check that the stream is well formed; use
+/// `parse_stream` for that. A `}` outside of any group is ignored.
+#[derive(Debug, Clone, Default)]
+pub struct StreamProcessor {
+  position: u64,
+  depth: u64,
+  score: u64,
+  groups: u64,
+  garbage: u64,
+  in_garbage: bool,
+  cancel: bool,
+}
+
+impl StreamProcessor {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Process the next chunk of the stream.
+  pub fn push(&mut self, chunk: &[u8]) {
+    for &byte in chunk {
+      self.position += 1;
+      if self.cancel {
+        self.cancel = false;
+        continue;
       }
-      '{' => {
-        group += 1;
+      if self.in_garbage {
+        match byte {
+          b'!' => self.cancel = true,
+          b'>' => self.in_garbage = false,
+          // Count characters, not UTF-8 continuation bytes.
+          _ if byte & 0xC0 != 0x80 => self.garbage += 1,
+          _ => {}
+        }
+        continue;
       }
-      '}' => {
-        score += group;
-        group -= 1;
+      match byte {
+        b'<' => self.in_garbage = true,
+        b'{' => {
+          self.depth += 1;
+          self.groups += 1;
+          self.score += self.depth;
+        }
+        b'}' if self.depth > 0 => self.depth -= 1,
+        _ => {}
+      }
+    }
+  }
+
+  /// Process everything from a reader, one chunk at a time.
+  pub fn process<R: Read>(&mut self, mut reader: R) -> io::Result<()> {
+    let mut buffer = [0; CHUNK_SIZE];
+    loop {
+      match reader.read(&mut buffer) {
+        Ok(0) => return Ok(()),
+        Ok(n) => self.push(&buffer[..n]),
+        Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+        Err(e) => return Err(e),
       }
-      _ => {}
     }
   }
-  return (score, ngarbage);
+
+  /// The number of bytes processed so far.
+  pub fn position(&self) -> u64 {
+    self.position
+  }
+
+  /// The depth of the innermost open group.
+  pub fn depth(&self) -> u64 {
+    self.depth
+  }
+
+  /// The total score of the groups opened so far.
+  pub fn score(&self) -> u64 {
+    self.score
+  }
+
+  /// The number of groups opened so far.
+  pub fn groups(&self) -> u64 {
+    self.groups
+  }
+
+  /// The number of characters of garbage so far.
+  pub fn garbage(&self) -> u64 {
+    self.garbage
+  }
+
+  /// Is the processor inside some garbage?
+  pub fn in_garbage(&self) -> bool {
+    self.in_garbage
+  }
+
+  /// Has every group and garbage segment been closed?
+  pub fn is_complete(&self) -> bool {
+    self.depth == 0 && !self.in_garbage && !self.cancel
+  }
+}
+
+/// Process a whole stream, returning `(score, garbage count)`.
+pub fn procress_stream(text: &str) -> (u32, u32) {
+  let mut processor = StreamProcessor::new();
+  processor.push(text.as_bytes());
+  (processor.score() as u32, processor.garbage() as u32)
 }
 
 #[cfg(test)]
@@ -47,6 +391,163 @@ mod test {
     assert_eq!(procress_stream("{{<ab>},{<ab>},{<ab>},{<ab>}}"), (9, 8));
   }
 
+  #[test]
+  fn parse_matches_flat_processing() {
+    for stream in vec![
+      "{}",
+      "{{{}}}",
+      "{{},{}}",
+      "{{{},{},{{}}}}",
+      "{<a>,<a>,<a>,<a>}",
+      "{{<ab>},{<ab>},{<ab>},{<ab>}}",
+      "{{<!!>},{<!!>},{<!!>},{<!!>}}",
+      "{{<a!>},{<a!>},{<a!>},{<ab>}}",
+      "<{o\"i!a,<{i<a>",
+      "<!!!>>",
+    ] {
+      let tree = parse_stream(stream).unwrap();
+      let (score, garbage) = procress_stream(stream);
+      assert_eq!(tree.score(), score, "scoring {}", stream);
+      assert_eq!(tree.garbage() as u32, garbage, "counting garbage in {}", stream);
+    }
+  }
+
+  #[test]
+  fn process_in_chunks() {
+    for stream in vec![
+      "{{<ab>},{<ab>},{<ab>},{<ab>}}",
+      "{{<!!>},{<!!>},{<!!>},{<!!>}}",
+      "{{<a!>},{<a!>},{<a!>},{<ab>}}",
+      "{<{o\"i!a,<{i<a>,{<é!ü>}}",
+    ] {
+      let mut whole = StreamProcessor::new();
+      whole.push(stream.as_bytes());
+      assert!(whole.is_complete());
+
+      for split in 0..stream.len() + 1 {
+        let (left, right) = stream.as_bytes().split_at(split);
+        let mut processor = StreamProcessor::new();
+        processor.push(left);
+        processor.push(right);
+        assert_eq!(processor.score(), whole.score(), "{} split at {}", stream, split);
+        assert_eq!(processor.garbage(), whole.garbage(), "{} split at {}", stream, split);
+      }
+    }
+  }
+
+  #[test]
+  fn process_running_totals() {
+    let mut processor = StreamProcessor::new();
+    processor.push(b"{{<a!");
+    assert_eq!(processor.depth(), 2);
+    assert_eq!(processor.score(), 3);
+    assert!(processor.in_garbage());
+    processor.push(b">b>},{");
+    assert_eq!(processor.garbage(), 2);
+    assert!(!processor.in_garbage());
+    assert_eq!(processor.score(), 5);
+    assert!(!processor.is_complete());
+    processor.push(b"}}");
+    assert!(processor.is_complete());
+    assert_eq!(processor.groups(), 3);
+    assert_eq!(processor.position(), 13);
+  }
+
+  #[test]
+  fn process_reader() {
+    let mut stream = String::from("{");
+    for _ in 0..10000 {
+      stream.push_str("{<a!>b>,{}},");
+    }
+    stream.push_str("{}}");
+
+    let mut processor = StreamProcessor::new();
+    processor.process(io::Cursor::new(stream.as_bytes())).unwrap();
+    assert!(processor.is_complete());
+    assert_eq!(processor.score(), 1 + 10000 * (2 + 3) + 2);
+    assert_eq!(processor.garbage(), 10000 * 2);
+  }
+
+  #[test]
+  fn parse_tree() {
+    let tree = parse_stream("{{<ab>},{<!>a>}}\n").unwrap();
+    assert_eq!(tree.span(), Span { start: 0, end: 16 });
+    assert_eq!(tree.groups(), 3);
+
+    let root = match tree {
+      Node::Group(g) => g,
+      _ => panic!("Expected a group"),
+    };
+    assert_eq!(root.children().len(), 2);
+    match root.children()[1] {
+      Node::Group(ref g) => {
+        assert_eq!(g.span(), Span { start: 8, end: 15 });
+        match g.children()[0] {
+          Node::Garbage(ref garbage) => {
+            assert_eq!(garbage.span(), Span { start: 9, end: 14 });
+            assert_eq!(garbage.cancelled(), &[11]);
+            assert_eq!(garbage.count(), 1);
+          }
+          _ => panic!("Expected garbage"),
+        }
+      }
+      _ => panic!("Expected a group"),
+    }
+  }
+
+  #[test]
+  fn parse_errors() {
+    assert_eq!(
+      parse_stream("{{}"),
+      Err(StreamError::UnterminatedGroup { start: 0 })
+    );
+    assert_eq!(
+      parse_stream("{<ab!>}"),
+      Err(StreamError::UnterminatedGarbage { start: 1 })
+    );
+    assert_eq!(
+      parse_stream("{}}"),
+      Err(StreamError::UnbalancedBrace { position: 2 })
+    );
+    assert_eq!(
+      parse_stream("{{}a}"),
+      Err(StreamError::UnexpectedCharacter {
+        position: 3,
+        found: 'a',
+        expected: "',' or '}'",
+      })
+    );
+    assert_eq!(
+      parse_stream("{,}"),
+      Err(StreamError::UnexpectedCharacter {
+        position: 1,
+        found: ',',
+        expected: "'{' or '<'",
+      })
+    );
+    assert_eq!(parse_stream(""), Err(StreamError::UnexpectedEnd));
+  }
+
+  #[test]
+  fn parse_deep_streams() {
+    let nested = |depth: usize| "{".repeat(depth) + &"}".repeat(depth);
+    assert_eq!(parse_stream(&nested(MAX_DEPTH)).unwrap().groups(), MAX_DEPTH);
+    assert_eq!(
+      parse_stream(&nested(MAX_DEPTH + 1)),
+      Err(StreamError::TooDeep {
+        position: MAX_DEPTH,
+        max: MAX_DEPTH,
+      })
+    );
+    assert_eq!(
+      parse_stream(&"{".repeat(1_000_000)),
+      Err(StreamError::TooDeep {
+        position: MAX_DEPTH,
+        max: MAX_DEPTH,
+      })
+    );
+  }
+
   #[test]
   fn try_garbage_stream() {
     assert_eq!(procress_stream("<>").1, 0);
@@ -14,6 +14,24 @@ where
 {
   values: HashMap<String, T>,
   default: T,
+  history: Option<Vec<Write<T>>>,
+  step: usize,
+}
+
+/// A single recorded write to a register.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Write<T> {
+  /// The step during which the write happened.
+  pub step: usize,
+
+  /// The register written to.
+  pub register: String,
+
+  /// The value of the register before the write.
+  pub old: T,
+
+  /// The value written.
+  pub new: T,
 }
 
 /// Arguments contain either a value
@@ -50,8 +68,58 @@ where
     Registers {
       values: HashMap::new(),
       default: default,
+      history: None,
+      step: 0,
     }
   }
+
+  /// Create registers which record every write made with `set`.
+  pub fn with_history(default: T) -> Registers<T> {
+    Registers {
+      history: Some(Vec::new()),
+      ..Registers::new(default)
+    }
+  }
+
+  /// The recorded writes, if history is being recorded.
+  /// Writes made through `get_mut` are not recorded.
+  pub fn history(&self) -> Option<&[Write<T>]> {
+    self.history.as_deref()
+  }
+
+  /// Move on to the next step. Writes are recorded with the
+  /// step they happen in, e.g. the instruction being executed.
+  pub fn tick(&mut self) {
+    self.step += 1;
+  }
+
+  /// The current step.
+  pub fn step(&self) -> usize {
+    self.step
+  }
+
+  /// Write a value to a register, returning the old value.
+  /// Nothing happens if the argument is a literal value.
+  pub fn set(&mut self, argument: &Argument<T>, value: T) -> Option<T> {
+    let register = match *argument {
+      Argument::Register(s) => s,
+      Argument::Value(_) => return None,
+    };
+    let old = self
+      .values
+      .insert(register.to_string(), value)
+      .unwrap_or(self.default);
+    if let Some(ref mut history) = self.history {
+      history.push(Write {
+        step: self.step,
+        register: register.to_string(),
+        old,
+        new: value,
+      });
+    }
+    Some(old)
+  }
+
   pub fn hmap(&self) -> &HashMap<String, T> {
     return &self.values;
   }
@@ -71,6 +139,55 @@ where
   }
 }
 
+impl<T> Registers<T>
+where
+  T: str::FromStr + Copy + Clone + Ord,
+{
+  /// The largest value each register has ever held,
+  /// from the recorded history and the current values.
+  pub fn high_water(&self) -> HashMap<&str, T> {
+    let mut marks: HashMap<&str, T> = self
+      .values
+      .iter()
+      .map(|(k, &v)| (k.as_str(), v))
+      .collect();
+    for write in self.history().unwrap_or(&[]) {
+      let mark = marks.entry(write.register.as_str()).or_insert(write.new);
+      if write.new > *mark {
+        *mark = write.new;
+      }
+    }
+    marks
+  }
+
+  /// The largest value any register has ever held.
+  pub fn max_ever(&self) -> Option<T> {
+    self.high_water().values().cloned().max()
+  }
+
+  /// The first recorded write which took a
+  /// register above `threshold`.
+  pub fn first_above(&self, threshold: T) -> Option<&Write<T>> {
+    self
+      .history()
+      .unwrap_or(&[])
+      .iter()
+      .find(|w| w.new > threshold && w.old <= threshold)
+  }
+
+  /// The current value of every register,
+  /// sorted from largest to smallest.
+  pub fn report(&self) -> Vec<(&str, T)> {
+    let mut report: Vec<(&str, T)> = self
+      .values
+      .iter()
+      .map(|(k, &v)| (k.as_str(), v))
+      .collect();
+    report.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+    report
+  }
+}
+
 #[cfg(test)]
 mod test {
 
@@ -103,6 +220,48 @@ mod test {
     }
   }
 
+  #[test]
+  fn record_history() {
+    let mut registry: Registers<i32> = Registers::with_history(0);
+    let a = Argument::Register("a");
+    let b = Argument::Register("b");
+    assert_eq!(registry.set(&a, 5), Some(0));
+    registry.tick();
+    assert_eq!(registry.set(&b, 3), Some(0));
+    assert_eq!(registry.set(&Argument::Value(1), 3), None);
+    registry.tick();
+    assert_eq!(registry.set(&a, -2), Some(5));
+
+    let history = registry.history().unwrap();
+    assert_eq!(history.len(), 3);
+    assert_eq!(
+      history[2],
+      Write {
+        step: 2,
+        register: "a".to_string(),
+        old: 5,
+        new: -2,
+      }
+    );
+
+    assert_eq!(registry.high_water().get("a"), Some(&5));
+    assert_eq!(registry.high_water().get("b"), Some(&3));
+    assert_eq!(registry.max_ever(), Some(5));
+    assert_eq!(registry.first_above(4).map(|w| w.step), Some(0));
+    assert_eq!(registry.first_above(2).map(|w| w.step), Some(0));
+    assert_eq!(registry.first_above(5), None);
+    assert_eq!(registry.report(), vec![("b", 3), ("a", -2)]);
+  }
+
+  #[test]
+  fn no_history() {
+    let mut registry: Registers<i32> = Registers::new(0);
+    registry.set(&Argument::Register("a"), 5);
+    registry.set(&Argument::Register("a"), 1);
+    assert!(registry.history().is_none());
+    assert_eq!(registry.max_ever(), Some(1));
+  }
+
   #[test]
   fn increment_registry() {
     let cond: Argument<i32> = "5".into();
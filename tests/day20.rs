@@ -13,7 +13,10 @@ fn part_one() {
     .lines()
     .map(|line| day20::Particle::parse(&line.unwrap()).unwrap())
     .collect();
-  assert_eq!(day20::find_closest(particles.clone(), false), 376);
+  assert_eq!(
+    day20::find_closest(particles.clone(), false),
+    day20::ParticleId(376)
+  );
 }
 
 #[test]
@@ -26,8 +29,19 @@ fn part_two() {
     .collect();
   let (_n, ps) = day20::simulate(particles.clone(), None, true)
     .skip(100)
-    .skip_while(|&(_i, ref ps)| ps.iter().all(|x| x.settled()))
+    .skip_while(|&(_i, ref ps)| ps.iter().all(|&(_, x)| x.settled()))
     .nth(100)
     .unwrap();
   assert_eq!(ps.len(), 574);
 }
+
+#[test]
+fn part_two_exact() {
+  let f = File::open("puzzles/20/input.txt").expect("file not found");
+  let reader = BufReader::new(f);
+  let particles: Vec<day20::Particle> = reader
+    .lines()
+    .map(|line| day20::Particle::parse(&line.unwrap()).unwrap())
+    .collect();
+  assert_eq!(day20::survivors(&particles).len(), 574);
+}
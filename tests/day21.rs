@@ -34,3 +34,13 @@ fn part_two() {
     2335049
   );
 }
+
+#[test]
+fn part_two_counting() {
+  let rulebook = load_patterns()
+    .and_then(|patterns| day21::Rulebook::new(&patterns))
+    .unwrap();
+  let start = day21::Patch::default();
+  assert_eq!(rulebook.count_on(&start, 5).unwrap(), 190);
+  assert_eq!(rulebook.count_on(&start, 18).unwrap(), 2335049);
+}